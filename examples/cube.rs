@@ -1,6 +1,7 @@
 use bevy::{
     input::{keyboard::KeyboardInput, ButtonState},
-    prelude::*, render::render_graph::RenderGraph,
+    prelude::*,
+    render::render_graph::RenderGraph,
 };
 use bevy_jfa::{CameraOutline, Outline, OutlinePlugin, OutlineSettings, OutlineStyle};
 
@@ -14,7 +15,11 @@ fn setup(
     mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
     let mesh = meshes.add(Mesh::from(shape::Cube { size: 1.0 }));
-    let sphere = meshes.add(Mesh::from(shape::UVSphere { radius: 1.0, sectors: 8, stacks: 8 }));
+    let sphere = meshes.add(Mesh::from(shape::UVSphere {
+        radius: 1.0,
+        sectors: 8,
+        stacks: 8,
+    }));
     let material = materials.add(StandardMaterial {
         base_color: Color::INDIGO,
         perceptual_roughness: 0.25,
@@ -62,8 +67,11 @@ fn setup(
             enabled: true,
             style: outline_styles.add(OutlineStyle {
                 color: Color::hex("b4a2c8").unwrap(),
+                inner_color: Color::WHITE,
                 width: 33.0,
+                ..default()
             }),
+            layers: None,
         });
 
     commands.spawn(PointLightBundle {
@@ -79,9 +87,7 @@ fn setup(
     });
 }
 
-fn rotate_cube(
-    time: Res<Time>,
-    mut query: Query<(&mut Transform, &RotationAxis), With<Outline>>) {
+fn rotate_cube(time: Res<Time>, mut query: Query<(&mut Transform, &RotationAxis), With<Outline>>) {
     let delta = time.delta_seconds();
 
     for (mut xform, rot) in query.iter_mut() {
@@ -100,8 +106,7 @@ fn handle_keys(mut settings: ResMut<OutlineSettings>, mut keys: EventReader<Keyb
 
 fn main() {
     let mut app = App::new();
-    app
-        .add_plugins(DefaultPlugins)
+    app.add_plugins(DefaultPlugins)
         .add_plugins(OutlinePlugin)
         // .insert_resource(Msaa::Off)
         .add_systems(Startup, setup)
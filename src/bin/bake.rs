@@ -0,0 +1,268 @@
+//! Bakes signed distance field textures from PNG images.
+//!
+//! Each input image is thresholded into a mask, which is turned into a signed
+//! distance field with the CPU jump flood in [`bevy_jfa::cpu`]. The distance is
+//! mapped to `[0, 1]` with 0.5 on the edge of the mask, values above 0.5
+//! inside, and 0 or 1 at `spread` pixels from the edge.
+//!
+//! Run with `--help` for the available options.
+
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use bevy_jfa::{cpu, JumpSchedule};
+use image::{GrayImage, ImageBuffer, Luma};
+
+const USAGE: &str = "\
+Bakes signed distance field textures from PNG images.
+
+USAGE:
+    bevy_jfa-bake [OPTIONS] <INPUT> <OUTPUT>
+
+If INPUT is a directory, every PNG image in it is baked into the directory
+OUTPUT, keeping the file names.
+
+OPTIONS:
+    --channel <alpha|luminance>  Channel thresholded into the mask [default: alpha]
+    --threshold <VALUE>          Channel value in [0, 1] at which a pixel is inside
+                                 the mask [default: 0.5]
+    --spread <PIXELS>            Distance from the edge, in input pixels, mapped to
+                                 0 and 1 [default: 8]
+    --correction <PASSES>        Number of correction passes run after the jump
+                                 flood [default: 2]
+    --bits <8|16>                Bit depth of the output images [default: 8]
+    --downscale <FACTOR>         Integer factor by which to shrink the output
+                                 [default: 1]
+    -h, --help                   Prints this message
+";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Channel {
+    Alpha,
+    Luminance,
+}
+
+#[derive(Clone, Debug)]
+struct Options {
+    channel: Channel,
+    threshold: f32,
+    spread: f32,
+    correction: u32,
+    bits: u32,
+    downscale: u32,
+    input: PathBuf,
+    output: PathBuf,
+}
+
+impl Options {
+    /// Parses the command-line arguments, or returns `None` if help was
+    /// requested.
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+        let mut channel = Channel::Alpha;
+        let mut threshold = 0.5;
+        let mut spread = 8.0;
+        let mut correction = 2;
+        let mut bits = 8;
+        let mut downscale = 1;
+        let mut paths = Vec::new();
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("missing value for `{name}`"))
+            };
+
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "--channel" => {
+                    channel = match value(&arg)?.as_str() {
+                        "alpha" => Channel::Alpha,
+                        "luminance" => Channel::Luminance,
+                        other => return Err(format!("unknown channel `{other}`")),
+                    }
+                }
+                "--threshold" => threshold = parse_value(&arg, &value(&arg)?)?,
+                "--spread" => spread = parse_value(&arg, &value(&arg)?)?,
+                "--correction" => correction = parse_value(&arg, &value(&arg)?)?,
+                "--bits" => bits = parse_value(&arg, &value(&arg)?)?,
+                "--downscale" => downscale = parse_value(&arg, &value(&arg)?)?,
+                other if other.starts_with('-') => {
+                    return Err(format!("unknown option `{other}`"));
+                }
+                _ => paths.push(PathBuf::from(arg)),
+            }
+        }
+
+        if !(0.0..=1.0).contains(&threshold) {
+            return Err("`--threshold` must be between 0 and 1".into());
+        }
+        if spread <= 0.0 {
+            return Err("`--spread` must be positive".into());
+        }
+        if bits != 8 && bits != 16 {
+            return Err("`--bits` must be 8 or 16".into());
+        }
+        if downscale == 0 {
+            return Err("`--downscale` must be at least 1".into());
+        }
+
+        let [input, output]: [PathBuf; 2] = paths
+            .try_into()
+            .map_err(|_| "expected an input and an output path".to_string())?;
+
+        Ok(Some(Options {
+            channel,
+            threshold,
+            spread,
+            correction,
+            bits,
+            downscale,
+            input,
+            output,
+        }))
+    }
+
+    /// Returns the jump distances for the flood.
+    ///
+    /// Pixels further than `spread` from the edge are clamped, so the flood
+    /// only needs to reach that far. The correction passes halve down to a
+    /// jump of 1, e.g. two passes give JFA+2.
+    fn steps(&self) -> Vec<u32> {
+        let mut steps = JumpSchedule::Standard.steps(self.spread.ceil() + 1.0);
+        steps.extend((0..self.correction).rev().map(|exp| 1 << exp.min(31)));
+        steps
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value `{value}` for `{name}`"))
+}
+
+fn main() -> ExitCode {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            print!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("error: {e}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match run(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(options: &Options) -> Result<(), Box<dyn Error>> {
+    if !options.input.is_dir() {
+        return bake(options, &options.input, &options.output);
+    }
+
+    fs::create_dir_all(&options.output)?;
+
+    let mut inputs = Vec::new();
+    for entry in fs::read_dir(&options.input)? {
+        let path = entry?.path();
+        let is_png = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+        if path.is_file() && is_png {
+            inputs.push(path);
+        }
+    }
+    inputs.sort();
+
+    for input in inputs {
+        let output = options.output.join(input.file_name().unwrap());
+        bake(options, &input, &output)?;
+    }
+
+    Ok(())
+}
+
+/// Bakes the image at `input` into a signed distance field at `output`.
+fn bake(options: &Options, input: &Path, output: &Path) -> Result<(), Box<dyn Error>> {
+    let image = image::open(input)
+        .map_err(|e| format!("failed to read {}: {e}", input.display()))?
+        .into_rgba32f();
+    let (width, height) = image.dimensions();
+
+    let inside: Vec<bool> = image
+        .pixels()
+        .map(|pixel| {
+            let [r, g, b, a] = pixel.0;
+            let value = match options.channel {
+                Channel::Alpha => a,
+                Channel::Luminance => 0.2126 * r + 0.7152 * g + 0.0722 * b,
+            };
+            value >= options.threshold
+        })
+        .collect();
+
+    let sdf = cpu::signed_distance(&inside, width, height, &options.steps());
+    let (out_width, out_height, sdf) = downscale(&sdf, width, height, options.downscale);
+
+    // Inside is above 0.5, matching the usual convention for SDF fonts.
+    let encode = |dist: f32| (0.5 - 0.5 * dist / options.spread).clamp(0.0, 1.0);
+
+    let result = if options.bits == 16 {
+        ImageBuffer::<Luma<u16>, _>::from_fn(out_width, out_height, |x, y| {
+            let value = encode(sdf[(y * out_width + x) as usize]);
+            Luma([(value * u16::MAX as f32).round() as u16])
+        })
+        .save(output)
+    } else {
+        GrayImage::from_fn(out_width, out_height, |x, y| {
+            let value = encode(sdf[(y * out_width + x) as usize]);
+            Luma([(value * u8::MAX as f32).round() as u8])
+        })
+        .save(output)
+    };
+    result.map_err(|e| format!("failed to write {}: {e}", output.display()))?;
+
+    println!("{} -> {}", input.display(), output.display());
+    Ok(())
+}
+
+/// Shrinks a distance field by an integer factor, averaging each block of
+/// pixels.
+///
+/// Returns the new width, height and distances. Partial blocks at the right
+/// and bottom edges average the pixels they contain.
+fn downscale(sdf: &[f32], width: u32, height: u32, factor: u32) -> (u32, u32, Vec<f32>) {
+    if factor == 1 {
+        return (width, height, sdf.to_vec());
+    }
+
+    let out_width = width.div_ceil(factor);
+    let out_height = height.div_ceil(factor);
+
+    let mut out = Vec::with_capacity((out_width * out_height) as usize);
+    for oy in 0..out_height {
+        for ox in 0..out_width {
+            let xs = ox * factor..((ox + 1) * factor).min(width);
+            let ys = oy * factor..((oy + 1) * factor).min(height);
+            let count = xs.len() * ys.len();
+
+            let sum: f32 = ys
+                .flat_map(|y| xs.clone().map(move |x| sdf[(y * width + x) as usize]))
+                .sum();
+            out.push(sum / count as f32);
+        }
+    }
+
+    (out_width, out_height, out)
+}
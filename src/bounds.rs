@@ -0,0 +1,248 @@
+use bevy::{
+    math::{Rect, URect, Vec3A},
+    prelude::*,
+    render::{
+        camera::ExtractedCamera,
+        primitives::Aabb,
+        render_phase::{PhaseItem, RenderPhase, TrackedRenderPass},
+        view::ExtractedView,
+    },
+};
+
+use crate::{
+    material::ViewOutlineComposite, resources::OutlineResources, ExtractedOutline, MeshMask,
+};
+
+/// Screen-space bounds of the outlined meshes in a view, padded by the outline
+/// width.
+///
+/// Every pass in the outline graph restricts rasterization to this rectangle
+/// with a scissor rect. The bounds never extend past the viewport of the view,
+/// so outlines don't spill into other viewports sharing the target.
+#[derive(Clone, Copy, Debug, Component)]
+pub struct ViewOutlineBounds {
+    rect: URect,
+}
+
+impl ViewOutlineBounds {
+    /// Returns the bounds in physical pixels of the outline targets.
+    pub fn rect(&self) -> URect {
+        self.rect
+    }
+
+    /// Returns whether no pixels of the view can be covered by an outline.
+    pub fn is_empty(&self) -> bool {
+        self.rect.is_empty()
+    }
+
+    /// Sets the scissor rect of `pass` to these bounds.
+    pub fn set_scissor(&self, pass: &mut TrackedRenderPass) {
+        pass.set_scissor_rect(
+            self.rect.min.x,
+            self.rect.min.y,
+            self.rect.width(),
+            self.rect.height(),
+        );
+    }
+}
+
+type OutlineBoundsViewQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static ExtractedView,
+        &'static ExtractedCamera,
+        &'static RenderPhase<MeshMask>,
+        Option<&'static ViewOutlineComposite>,
+    ),
+>;
+
+pub fn prepare_outline_bounds(
+    mut commands: Commands,
+    res: Res<OutlineResources>,
+    outlines: Query<&ExtractedOutline>,
+    views: OutlineBoundsViewQuery,
+) {
+    for (entity, view, camera, phase, composite) in views.iter() {
+        let Some(dims) = camera
+            .target
+            .as_ref()
+            .and_then(|target| res.targets.get(target))
+            .map(|target| target.dimensions)
+        else {
+            continue;
+        };
+        let target = Rect::new(0.0, 0.0, dims.width, dims.height);
+
+        let viewport = view.viewport.as_vec4();
+        let viewport = Rect::new(
+            viewport.x,
+            viewport.y,
+            viewport.x + viewport.z,
+            viewport.y + viewport.w,
+        )
+        .intersect(target);
+
+        let padding = composite.map(|composite| (2.0 * composite.max_width).ceil() + 1.0);
+
+        let view_proj = view
+            .view_projection
+            .unwrap_or_else(|| view.projection * view.transform.compute_matrix().inverse());
+
+        let mut bounds: Option<Rect> = None;
+        let mut complete = true;
+        for item in phase.items.iter() {
+            let Ok(outline) = outlines.get(item.entity()) else {
+                continue;
+            };
+
+            match item_bounds(view, &view_proj, item, outline) {
+                Some(rect) => bounds = Some(bounds.map_or(rect, |bounds| bounds.union(rect))),
+                None => {
+                    complete = false;
+                    break;
+                }
+            }
+        }
+
+        // Fall back to the whole viewport if any bounds are unknown.
+        let rect = match (bounds, padding) {
+            (Some(bounds), Some(padding)) if complete => bounds.inset(padding),
+            _ => viewport,
+        };
+
+        let rect = rect.intersect(viewport);
+        commands.entity(entity).insert(ViewOutlineBounds {
+            rect: URect::from_corners(rect.min.floor().as_uvec2(), rect.max.ceil().as_uvec2()),
+        });
+    }
+}
+
+/// Returns the bounds of a mask phase item in physical pixels.
+///
+/// Returns `None` if the item may be drawn outside the AABB of its mesh, as
+/// mask materials can move vertices in their vertex shader.
+fn item_bounds(
+    view: &ExtractedView,
+    view_proj: &Mat4,
+    item: &MeshMask,
+    outline: &ExtractedOutline,
+) -> Option<Rect> {
+    if item.material.is_some() {
+        return None;
+    }
+
+    project_aabb(view, view_proj, outline)
+}
+
+/// Projects the AABB of an outlined mesh to physical pixels.
+///
+/// Returns `None` if the mesh has no AABB or crosses the camera plane.
+fn project_aabb(
+    view: &ExtractedView,
+    view_proj: &Mat4,
+    outline: &ExtractedOutline,
+) -> Option<Rect> {
+    let aabb: &Aabb = outline.aabb.as_ref()?;
+    let viewport = view.viewport.as_vec4();
+    let model_view_proj = *view_proj * outline.transform;
+
+    let mut rect: Option<Rect> = None;
+    for i in 0..8 {
+        let corner = Vec3A::new(
+            if i & 1 == 0 { -1.0 } else { 1.0 },
+            if i & 2 == 0 { -1.0 } else { 1.0 },
+            if i & 4 == 0 { -1.0 } else { 1.0 },
+        );
+        let local = aabb.center + corner * aabb.half_extents;
+        let clip = model_view_proj * local.extend(1.0);
+        if clip.w <= 0.0 {
+            return None;
+        }
+
+        let ndc = clip.truncate().truncate() / clip.w;
+        let pix = Vec2::new(
+            viewport.x + (ndc.x * 0.5 + 0.5) * viewport.z,
+            viewport.y + (0.5 - ndc.y * 0.5) * viewport.w,
+        );
+
+        rect = Some(match rect {
+            Some(rect) => rect.union_point(pix),
+            None => Rect::from_corners(pix, pix),
+        });
+    }
+
+    rect
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{
+        pbr::StandardMaterial,
+        render::{
+            render_phase::{DrawFunctions, RenderCommandState, SetItemPipeline},
+            render_resource::CachedRenderPipelineId,
+            view::RenderLayers,
+        },
+    };
+
+    use super::*;
+
+    #[test]
+    fn mask_materials_leave_bounds_unknown() {
+        let mut world = World::new();
+        let draw_function = DrawFunctions::<MeshMask>::default()
+            .write()
+            .add(RenderCommandState::<MeshMask, SetItemPipeline>::new(
+                &mut world,
+            ));
+
+        let view = ExtractedView {
+            projection: Mat4::perspective_infinite_reverse_rh(
+                std::f32::consts::FRAC_PI_2,
+                1.0,
+                0.1,
+            ),
+            transform: GlobalTransform::from_xyz(0.0, 0.0, 5.0),
+            view_projection: None,
+            hdr: false,
+            viewport: UVec4::new(0, 0, 100, 100),
+            color_grading: default(),
+        };
+        let view_proj = view.projection * view.transform.compute_matrix().inverse();
+        let outline = ExtractedOutline {
+            mesh: Handle::default(),
+            transform: Mat4::IDENTITY,
+            layers: RenderLayers::default(),
+            aabb: Some(Aabb {
+                center: Vec3A::ZERO,
+                half_extents: Vec3A::ONE,
+            }),
+            id: 1,
+            intensity: 1.0,
+            color: None,
+            width: None,
+            blend_mode: None,
+        };
+        let mut item = MeshMask {
+            distance: 5.0,
+            mesh: outline.mesh.id(),
+            material: None,
+            pipeline: CachedRenderPipelineId::INVALID,
+            entity: Entity::PLACEHOLDER,
+            draw_function,
+            batch_range: 0..1,
+            dynamic_offset: None,
+        };
+
+        // The near face of the cube is 4 units from the camera.
+        let rect = item_bounds(&view, &view_proj, &item, &outline).unwrap();
+        assert!((rect.min - Vec2::splat(37.5)).abs().max_element() < 1e-3);
+        assert!((rect.max - Vec2::splat(62.5)).abs().max_element() < 1e-3);
+
+        // Mask materials can move vertices outside of the AABB.
+        item.material = Some(Handle::<StandardMaterial>::weak_from_u128(1).id().untyped());
+        assert_eq!(item_bounds(&view, &view_proj, &item, &outline), None);
+    }
+}
@@ -0,0 +1,536 @@
+//! CPU reference implementation of the outline distance field.
+//!
+//! These functions mirror the GPU passes on plain 2D grids, so changes to the
+//! algorithm can be checked without a GPU:
+//!
+//! - [`init_seeds`] matches the seed selection of `jfa_init.wgsl`: a pixel is
+//!   a seed if any of its 8 neighbors has a different object ID.
+//! - [`edge_offset`] matches the sub-pixel seed offset of `jfa_init.wgsl`,
+//!   estimated from the mask coverage. Seed maps only hold whole pixels, so
+//!   the other functions model seeds at pixel centers.
+//! - [`jump_flood`] matches `jfa.wgsl`: each step samples a 3x3 grid of
+//!   neighbors `dist` pixels apart, clamped to the edges of the grid, and keeps
+//!   the nearest seed.
+//! - [`exact_distance`] computes the exact Euclidean distance transform of the
+//!   seeds, which [`JfaError::measure`] compares against a flooded seed map.
+//!
+//! All distances are measured between pixel centers, in pixels.
+
+use bevy::math::{UVec2, Vec2};
+
+/// A grid storing the nearest known seed of every pixel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SeedMap {
+    width: u32,
+    height: u32,
+    seeds: Vec<Option<UVec2>>,
+}
+
+impl SeedMap {
+    /// Creates a seed map of the given size without any seeds.
+    pub fn new(width: u32, height: u32) -> SeedMap {
+        SeedMap {
+            width,
+            height,
+            seeds: vec![None; (width * height) as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the nearest known seed of the pixel at `(x, y)`.
+    pub fn get(&self, x: u32, y: u32) -> Option<UVec2> {
+        self.seeds[self.index(x, y)]
+    }
+
+    /// Sets the nearest known seed of the pixel at `(x, y)`.
+    pub fn set(&mut self, x: u32, y: u32, seed: Option<UVec2>) {
+        let index = self.index(x, y);
+        self.seeds[index] = seed;
+    }
+
+    /// Returns whether the pixel at `(x, y)` is its own seed.
+    pub fn is_seed(&self, x: u32, y: u32) -> bool {
+        self.get(x, y) == Some(UVec2::new(x, y))
+    }
+
+    /// Returns the distance from the pixel at `(x, y)` to its nearest known
+    /// seed, or `None` if no seed has reached it.
+    pub fn distance(&self, x: u32, y: u32) -> Option<f32> {
+        self.get(x, y)
+            .map(|seed| distance2(UVec2::new(x, y), seed).sqrt())
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.width && y < self.height);
+        (y * self.width + x) as usize
+    }
+}
+
+/// Finds the initial seeds of a labeled mask.
+///
+/// `ids` holds one object ID per pixel in row-major order, with 0 for the
+/// background. As in the GPU init pass, every pixel with a neighbor of a
+/// different ID is a seed, which includes both sides of every edge.
+pub fn init_seeds(ids: &[u32], width: u32, height: u32) -> SeedMap {
+    assert_eq!(ids.len(), (width * height) as usize);
+
+    let load_id = |x: i64, y: i64| {
+        let x = x.clamp(0, width as i64 - 1);
+        let y = y.clamp(0, height as i64 - 1);
+        ids[(y * width as i64 + x) as usize]
+    };
+
+    let mut seeds = SeedMap::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let center = load_id(x as i64, y as i64);
+            let is_edge = (-1..=1)
+                .any(|dy| (-1..=1).any(|dx| load_id(x as i64 + dx, y as i64 + dy) != center));
+            if is_edge {
+                seeds.set(x, y, Some(UVec2::new(x, y)));
+            }
+        }
+    }
+
+    seeds
+}
+
+/// Returns the offset in pixels from the center of the pixel at `(x, y)` to
+/// the edge of a mask.
+///
+/// `coverage` holds the resolved coverage of every pixel in row-major order.
+/// As in the GPU init pass, the edge normal is the Sobel gradient of the
+/// coverage, and a pixel with coverage `c` is assumed to be `c - 0.5` pixels
+/// inside a straight edge. Where coverage doesn't change, the offset is zero.
+pub fn edge_offset(coverage: &[f32], width: u32, height: u32, x: u32, y: u32) -> Vec2 {
+    assert_eq!(coverage.len(), (width * height) as usize);
+
+    let load_coverage = |dx: i64, dy: i64| {
+        let x = (x as i64 + dx).clamp(0, width as i64 - 1);
+        let y = (y as i64 + dy).clamp(0, height as i64 - 1);
+        coverage[(y * width as i64 + x) as usize]
+    };
+
+    let sobel_x = load_coverage(1, -1) + 2.0 * load_coverage(1, 0) + load_coverage(1, 1)
+        - load_coverage(-1, -1)
+        - 2.0 * load_coverage(-1, 0)
+        - load_coverage(-1, 1);
+    let sobel_y = load_coverage(-1, 1) + 2.0 * load_coverage(0, 1) + load_coverage(1, 1)
+        - load_coverage(-1, -1)
+        - 2.0 * load_coverage(0, -1)
+        - load_coverage(1, -1);
+    let gradient = Vec2::new(sobel_x, sobel_y);
+
+    if gradient.length_squared() < 1e-4 {
+        return Vec2::ZERO;
+    }
+
+    // Coverage increases towards the inside of the mask.
+    let outward = -gradient.normalize();
+    outward * (load_coverage(0, 0) - 0.5)
+}
+
+/// Runs one jump flood pass per entry of `steps`, in order.
+///
+/// Each pass reads only the output of the previous pass, like the ping-pong
+/// textures of the GPU implementation.
+pub fn jump_flood(init: &SeedMap, steps: &[u32]) -> SeedMap {
+    let mut src = init.clone();
+    let mut dst = SeedMap::new(init.width, init.height);
+
+    for &dist in steps {
+        jump_flood_step(&src, &mut dst, dist);
+        std::mem::swap(&mut src, &mut dst);
+    }
+
+    src
+}
+
+/// Runs a single jump flood pass with a jump of `dist` pixels.
+pub fn jump_flood_step(src: &SeedMap, dst: &mut SeedMap, dist: u32) {
+    assert_eq!((src.width, src.height), (dst.width, dst.height));
+
+    let dist = dist as i64;
+    let max_x = src.width as i64 - 1;
+    let max_y = src.height as i64 - 1;
+
+    for y in 0..src.height {
+        for x in 0..src.width {
+            let pix = UVec2::new(x, y);
+            let mut nearest = None;
+            let mut min_dist2 = f32::INFINITY;
+
+            // Same sample order as `jfa.wgsl`, so ties resolve identically.
+            for dx in [-dist, 0, dist] {
+                for dy in [-dist, 0, dist] {
+                    let sx = (x as i64 + dx).clamp(0, max_x) as u32;
+                    let sy = (y as i64 + dy).clamp(0, max_y) as u32;
+                    let Some(seed) = src.get(sx, sy) else {
+                        continue;
+                    };
+
+                    let dist2 = distance2(pix, seed);
+                    if dist2 < min_dist2 {
+                        min_dist2 = dist2;
+                        nearest = Some(seed);
+                    }
+                }
+            }
+
+            dst.set(x, y, nearest);
+        }
+    }
+}
+
+/// Computes the exact distance from every pixel to the nearest seed of
+/// `seeds`.
+///
+/// Uses the separable algorithm of Felzenszwalb and Huttenlocher, "Distance
+/// Transforms of Sampled Functions". Pixels are infinitely far away if there
+/// are no seeds. The result is in row-major order.
+pub fn exact_distance(seeds: &SeedMap) -> Vec<f32> {
+    let width = seeds.width as usize;
+    let height = seeds.height as usize;
+
+    let mut dist2: Vec<f32> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            if seeds.is_seed(x as u32, y as u32) {
+                0.0
+            } else {
+                f32::INFINITY
+            }
+        })
+        .collect();
+
+    let mut column = vec![0.0; height];
+    for x in 0..width {
+        for (y, value) in column.iter_mut().enumerate() {
+            *value = dist2[y * width + x];
+        }
+        let transformed = distance_transform_1d(&column);
+        for (y, value) in transformed.into_iter().enumerate() {
+            dist2[y * width + x] = value;
+        }
+    }
+
+    for row in dist2.chunks_mut(width.max(1)) {
+        let transformed = distance_transform_1d(row);
+        row.copy_from_slice(&transformed);
+    }
+
+    dist2.into_iter().map(f32::sqrt).collect()
+}
+
+/// Computes the exact distance from every pixel to the nearest seed of
+/// `seeds` by testing every seed.
+///
+/// This is quadratic in the number of pixels and only meant for checking
+/// [`exact_distance`] on small grids.
+pub fn brute_force_distance(seeds: &SeedMap) -> Vec<f32> {
+    let all_seeds: Vec<UVec2> = (0..seeds.height)
+        .flat_map(|y| (0..seeds.width).map(move |x| UVec2::new(x, y)))
+        .filter(|pix| seeds.is_seed(pix.x, pix.y))
+        .collect();
+
+    (0..seeds.height)
+        .flat_map(|y| (0..seeds.width).map(move |x| UVec2::new(x, y)))
+        .map(|pix| {
+            all_seeds
+                .iter()
+                .map(|&seed| distance2(pix, seed))
+                .fold(f32::INFINITY, f32::min)
+                .sqrt()
+        })
+        .collect()
+}
+
+/// Error of a flooded seed map compared to the exact distance transform.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct JfaError {
+    /// Number of pixels whose distance differs from the exact distance.
+    pub wrong_pixels: usize,
+    /// Largest distance error of any pixel, in pixels.
+    pub max_error: f32,
+    /// Mean distance error over all pixels, in pixels.
+    pub mean_error: f32,
+}
+
+impl JfaError {
+    /// Compares the distances of `flooded` to `exact`, as returned by
+    /// [`exact_distance`].
+    ///
+    /// Pixels that were never reached by a seed count as wrong, but are
+    /// excluded from the error distances.
+    pub fn measure(flooded: &SeedMap, exact: &[f32]) -> JfaError {
+        assert_eq!(exact.len(), flooded.seeds.len());
+
+        let mut error = JfaError::default();
+        let mut total = 0.0;
+        for y in 0..flooded.height {
+            for x in 0..flooded.width {
+                let exact = exact[(y * flooded.width + x) as usize];
+                let Some(dist) = flooded.distance(x, y) else {
+                    if exact.is_finite() {
+                        error.wrong_pixels += 1;
+                    }
+                    continue;
+                };
+
+                let diff = (dist - exact).abs();
+                if diff > 1e-4 {
+                    error.wrong_pixels += 1;
+                }
+                error.max_error = error.max_error.max(diff);
+                total += diff;
+            }
+        }
+
+        if !flooded.seeds.is_empty() {
+            error.mean_error = total / flooded.seeds.len() as f32;
+        }
+        error
+    }
+}
+
+/// Computes a signed distance field of a binary mask with the jump flood
+/// passes in `steps`.
+///
+/// `inside` holds one value per pixel in row-major order. Distances are in
+/// pixels, negative inside the mask and positive outside. Since seeds lie on
+/// both sides of the edge, half a pixel is added to the distance to the
+/// nearest seed so that the zero crossing falls between pixel centers. Pixels
+/// without any seed, as in a blank mask, get an infinite distance.
+pub fn signed_distance(inside: &[bool], width: u32, height: u32, steps: &[u32]) -> Vec<f32> {
+    let ids: Vec<u32> = inside.iter().map(|&inside| inside as u32).collect();
+    let flooded = jump_flood(&init_seeds(&ids, width, height), steps);
+
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .zip(inside.iter())
+        .map(|((x, y), &inside)| {
+            let dist = flooded
+                .distance(x, y)
+                .map_or(f32::INFINITY, |dist| dist + 0.5);
+            if inside {
+                -dist
+            } else {
+                dist
+            }
+        })
+        .collect()
+}
+
+/// One-dimensional squared distance transform of `f`.
+fn distance_transform_1d(f: &[f32]) -> Vec<f32> {
+    let n = f.len();
+    let mut d = vec![f32::INFINITY; n];
+
+    // Locations of the parabolas in the lower envelope.
+    let mut v = vec![0usize; n];
+    // Boundaries between the parabolas in the lower envelope.
+    let mut z = vec![0.0f32; n + 1];
+    // Index of the rightmost parabola in the lower envelope.
+    let mut k = 0;
+
+    let Some(first) = f.iter().position(|value| value.is_finite()) else {
+        return d;
+    };
+    v[0] = first;
+    z[0] = f32::NEG_INFINITY;
+    z[1] = f32::INFINITY;
+
+    for q in first + 1..n {
+        if !f[q].is_finite() {
+            continue;
+        }
+
+        let intersection =
+            |p: usize| ((f[q] + (q * q) as f32) - (f[p] + (p * p) as f32)) / (2 * q - 2 * p) as f32;
+
+        let mut s = intersection(v[k]);
+        while s <= z[k] {
+            k -= 1;
+            s = intersection(v[k]);
+        }
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = f32::INFINITY;
+    }
+
+    k = 0;
+    for (q, value) in d.iter_mut().enumerate() {
+        while z[k + 1] < q as f32 {
+            k += 1;
+        }
+        let delta = q as f32 - v[k] as f32;
+        *value = delta * delta + f[v[k]];
+    }
+
+    d
+}
+
+fn distance2(a: UVec2, b: UVec2) -> f32 {
+    let delta = a.as_vec2() - b.as_vec2();
+    delta.dot(delta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::JumpSchedule;
+
+    /// A labeled mask with a filled rectangle of ID 1 and a disc of ID 2.
+    fn shapes(width: u32, height: u32) -> Vec<u32> {
+        let mut ids = vec![0; (width * height) as usize];
+        for y in 0..height {
+            for x in 0..width {
+                let index = (y * width + x) as usize;
+                if (4..12).contains(&x) && (6..20).contains(&y) {
+                    ids[index] = 1;
+                }
+                let (dx, dy) = (x as f32 - 22.0, y as f32 - 14.0);
+                if dx * dx + dy * dy < 36.0 {
+                    ids[index] = 2;
+                }
+            }
+        }
+        ids
+    }
+
+    #[test]
+    fn init_finds_edges_on_both_sides() {
+        let mut ids = vec![0; 25];
+        ids[12] = 7;
+        let seeds = init_seeds(&ids, 5, 5);
+
+        for y in 0..5 {
+            for x in 0..5 {
+                let near_center = (1..=3).contains(&x) && (1..=3).contains(&y);
+                assert_eq!(seeds.is_seed(x, y), near_center, "pixel ({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn edge_offset_moves_seeds_to_the_edge() {
+        // A vertical edge a quarter pixel right of the centers of column 3.
+        let coverage: Vec<f32> = (0..8 * 4)
+            .map(|i| match i % 8 {
+                0..=2 => 1.0,
+                3 => 0.75,
+                _ => 0.0,
+            })
+            .collect();
+
+        let offset = edge_offset(&coverage, 8, 4, 3, 1);
+        assert!((offset - Vec2::new(0.25, 0.0)).length() < 1e-5, "{offset}");
+        // Inside the mask, away from the edge.
+        assert_eq!(edge_offset(&coverage, 8, 4, 0, 1), Vec2::ZERO);
+        // Background pixels are moved back onto the edge.
+        let outside = edge_offset(&coverage, 8, 4, 4, 1);
+        assert!(outside.x < 0.0 && outside.y.abs() < 1e-5, "{outside}");
+    }
+
+    #[test]
+    fn init_without_edges_has_no_seeds() {
+        let seeds = init_seeds(&[3; 16], 4, 4);
+        let exact = exact_distance(&seeds);
+
+        assert!((0..4).all(|y| (0..4).all(|x| seeds.get(x, y).is_none())));
+        assert!(exact.iter().all(|dist| dist.is_infinite()));
+        assert_eq!(jump_flood(&seeds, &[2, 1]), seeds);
+    }
+
+    #[test]
+    fn exact_distance_matches_brute_force() {
+        let (width, height) = (32, 24);
+        let seeds = init_seeds(&shapes(width, height), width, height);
+
+        let exact = exact_distance(&seeds);
+        let brute = brute_force_distance(&seeds);
+        for (exact, brute) in exact.iter().zip(brute.iter()) {
+            assert!((exact - brute).abs() < 1e-4, "{exact} != {brute}");
+        }
+    }
+
+    #[test]
+    fn exact_distance_of_single_seed() {
+        let mut seeds = SeedMap::new(7, 5);
+        seeds.set(2, 1, Some(UVec2::new(2, 1)));
+
+        let exact = exact_distance(&seeds);
+        assert_eq!(exact[7 + 2], 0.0);
+        assert_eq!(exact[7 + 6], 4.0);
+        assert_eq!(exact[4 * 7 + 6], 5.0);
+    }
+
+    #[test]
+    fn jump_flood_reaches_every_pixel() {
+        let (width, height) = (32, 24);
+        let seeds = init_seeds(&shapes(width, height), width, height);
+        let steps = JumpSchedule::Standard.steps(width.max(height) as f32);
+        let flooded = jump_flood(&seeds, &steps);
+
+        for y in 0..height {
+            for x in 0..width {
+                let seed = flooded.get(x, y).expect("pixel not reached");
+                assert!(seeds.is_seed(seed.x, seed.y));
+            }
+        }
+    }
+
+    #[test]
+    fn jump_flood_is_close_to_exact() {
+        let (width, height) = (32, 24);
+        let seeds = init_seeds(&shapes(width, height), width, height);
+        let exact = exact_distance(&seeds);
+        let width_f = width.max(height) as f32;
+
+        let standard = JfaError::measure(
+            &jump_flood(&seeds, &JumpSchedule::Standard.steps(width_f)),
+            &exact,
+        );
+        let plus_two = JfaError::measure(
+            &jump_flood(&seeds, &JumpSchedule::PlusTwo.steps(width_f)),
+            &exact,
+        );
+
+        assert!(standard.max_error < 1.5, "{standard:?}");
+        assert!(
+            plus_two.wrong_pixels <= standard.wrong_pixels,
+            "{plus_two:?}"
+        );
+        assert!(plus_two.mean_error <= standard.mean_error, "{plus_two:?}");
+    }
+
+    #[test]
+    fn single_step_flood_matches_neighborhood() {
+        let mut seeds = SeedMap::new(3, 1);
+        seeds.set(0, 0, Some(UVec2::new(0, 0)));
+
+        let flooded = jump_flood(&seeds, &[1]);
+        assert_eq!(flooded.get(1, 0), Some(UVec2::new(0, 0)));
+        // Two pixels away is out of reach of a single jump of 1.
+        assert_eq!(flooded.get(2, 0), None);
+    }
+
+    #[test]
+    fn signed_distance_changes_sign_at_edge() {
+        // Left half inside, right half outside.
+        let inside: Vec<bool> = (0..8 * 4).map(|i| i % 8 < 4).collect();
+        let sdf = signed_distance(&inside, 8, 4, &JumpSchedule::PlusTwo.steps(8.0));
+
+        for row in sdf.chunks(8) {
+            assert_eq!(row, &[-3.5, -2.5, -1.5, -0.5, 0.5, 1.5, 2.5, 3.5]);
+        }
+    }
+}
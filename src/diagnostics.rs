@@ -0,0 +1,321 @@
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc, Mutex,
+};
+
+#[cfg(feature = "wgpu-profiler")]
+use bevy::render::{
+    render_resource::WgpuFeatures,
+    renderer::{RenderDevice, RenderQueue},
+};
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticId, Diagnostics, RegisterDiagnostic},
+    prelude::*,
+    render::renderer::RenderContext,
+};
+#[cfg(feature = "wgpu-profiler")]
+use wgpu_profiler::{GpuProfiler, GpuTimerScopeResult};
+
+/// The passes of the outline render graph, as reported to diagnostics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum OutlineStage {
+    Mask,
+    JfaInit,
+    Jfa,
+    Composite,
+}
+
+impl OutlineStage {
+    const ALL: [OutlineStage; 4] = [
+        OutlineStage::Mask,
+        OutlineStage::JfaInit,
+        OutlineStage::Jfa,
+        OutlineStage::Composite,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            OutlineStage::Mask => "outline_mask",
+            OutlineStage::JfaInit => "outline_jfa_init",
+            OutlineStage::Jfa => "outline_jfa",
+            OutlineStage::Composite => "outline_composite",
+        }
+    }
+}
+
+/// Counters shared between the main world and the render world.
+///
+/// The outline render graph increments these as it runs, and
+/// [`OutlineDiagnosticsPlugin`] drains them into Bevy's diagnostics once per
+/// frame.
+#[derive(Clone, Default, Resource)]
+pub(crate) struct OutlineStats(Arc<OutlineStatsInner>);
+
+#[derive(Default)]
+pub(crate) struct OutlineStatsInner {
+    outlined_views: AtomicU32,
+    skipped_views: AtomicU32,
+    // Render passes recorded per stage, indexed by `OutlineStage`.
+    passes: [AtomicU32; 4],
+    mask_draws: AtomicU32,
+    // GPU time in milliseconds per stage of the most recently resolved frame.
+    timings: Mutex<Option<[f64; 4]>>,
+    #[cfg(feature = "wgpu-profiler")]
+    profiler: Mutex<Option<GpuProfiler>>,
+}
+
+impl OutlineStats {
+    pub fn view_outlined(&self) {
+        self.0.outlined_views.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn view_skipped(&self) {
+        self.0.skipped_views.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records that a render pass of `stage` was encoded.
+    pub fn pass_recorded(&self, stage: OutlineStage) {
+        self.0.passes[stage as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records draw calls issued by the mask pass.
+    pub fn mask_draws_recorded(&self, draws: u32) {
+        self.0.mask_draws.fetch_add(draws, Ordering::Relaxed);
+    }
+
+    /// Opens a GPU timer scope around the passes of `stage`.
+    ///
+    /// Every call must be matched by a call to [`OutlineStats::end_stage`]
+    /// in the same node.
+    pub fn begin_stage(&self, stage: OutlineStage, render_context: &mut RenderContext) {
+        self.begin_scope(stage.label(), render_context);
+    }
+
+    /// Closes the scope opened by [`OutlineStats::begin_stage`] and resolves
+    /// its timestamp queries.
+    #[allow(unused_variables)]
+    pub fn end_stage(&self, render_context: &mut RenderContext) {
+        self.end_scope(render_context);
+
+        #[cfg(feature = "wgpu-profiler")]
+        if let Some(profiler) = self.0.profiler.lock().unwrap().as_mut() {
+            profiler.resolve_queries(render_context.command_encoder());
+        }
+    }
+
+    /// Opens a GPU timer scope nested inside the current stage.
+    #[allow(unused_variables)]
+    pub fn begin_scope(&self, label: &str, render_context: &mut RenderContext) {
+        #[cfg(feature = "wgpu-profiler")]
+        if let Some(profiler) = self.0.profiler.lock().unwrap().as_mut() {
+            let device = render_context.render_device().clone();
+            profiler.begin_scope(
+                label,
+                render_context.command_encoder(),
+                device.wgpu_device(),
+            );
+        }
+    }
+
+    /// Closes the innermost open GPU timer scope.
+    #[allow(unused_variables)]
+    pub fn end_scope(&self, render_context: &mut RenderContext) {
+        #[cfg(feature = "wgpu-profiler")]
+        if let Some(profiler) = self.0.profiler.lock().unwrap().as_mut() {
+            profiler.end_scope(render_context.command_encoder());
+        }
+    }
+}
+
+/// Creates the GPU profiler if the device supports timestamp queries.
+///
+/// Without timestamp queries only the pass counts are reported.
+#[cfg(feature = "wgpu-profiler")]
+pub(crate) fn init_outline_profiler(render_world: &World) {
+    let stats = render_world.resource::<OutlineStats>();
+    let device = render_world.resource::<RenderDevice>();
+    let queue = render_world.resource::<RenderQueue>();
+
+    // Scopes are written on the command encoder, between passes.
+    let features = device.features();
+    if !features.contains(WgpuFeatures::TIMESTAMP_QUERY) {
+        return;
+    }
+
+    let mut profiler = GpuProfiler::new(4, queue.get_timestamp_period(), features);
+    // Debug groups are already emitted by the render graph.
+    profiler.enable_debug_marker = false;
+    *stats.0.profiler.lock().unwrap() = Some(profiler);
+}
+
+/// Ends the profiler frame after the render graph has been submitted and
+/// collects the timings of any finished frames.
+#[cfg(feature = "wgpu-profiler")]
+pub(crate) fn end_outline_profiler_frame(stats: Res<OutlineStats>) {
+    let mut profiler = stats.0.profiler.lock().unwrap();
+    let Some(profiler) = profiler.as_mut() else {
+        return;
+    };
+
+    if profiler.end_frame().is_err() {
+        warn!("Outline GPU profiler scopes were left open or unresolved");
+        return;
+    }
+
+    while let Some(results) = profiler.process_finished_frame() {
+        let mut timings = [0.0; 4];
+        for result in results.iter() {
+            add_timings(&mut timings, result);
+        }
+        *stats.0.timings.lock().unwrap() = Some(timings);
+    }
+}
+
+#[cfg(feature = "wgpu-profiler")]
+fn add_timings(timings: &mut [f64; 4], result: &GpuTimerScopeResult) {
+    match OutlineStage::ALL
+        .iter()
+        .find(|stage| stage.label() == result.label)
+    {
+        // Stages of several views are summed.
+        Some(&stage) => {
+            timings[stage as usize] += (result.time.end - result.time.start) * 1000.0;
+        }
+        None => {
+            for nested in result.nested_scopes.iter() {
+                add_timings(timings, nested);
+            }
+        }
+    }
+}
+
+/// Adds diagnostics for the outline render graph.
+///
+/// With the `wgpu-profiler` feature enabled and an adapter that supports
+/// timestamp queries, the GPU time of each stage is reported in milliseconds.
+/// The number of render passes encoded for each stage, and the number of
+/// draw calls issued by the mask passes, are always reported.
+///
+/// Requires the [`OutlinePlugin`](crate::OutlinePlugin).
+#[derive(Default)]
+pub struct OutlineDiagnosticsPlugin;
+
+impl OutlineDiagnosticsPlugin {
+    /// Number of views for which outlines were rendered.
+    pub const OUTLINED_VIEWS: DiagnosticId =
+        DiagnosticId::from_u128(23035444583149955492491986003494462165);
+    /// Number of views for which the outline graph was skipped because no
+    /// outlined entities were visible.
+    pub const SKIPPED_VIEWS: DiagnosticId =
+        DiagnosticId::from_u128(155956689852607308646306169864753873435);
+    /// GPU time of the mask pass in milliseconds.
+    pub const MASK_MS: DiagnosticId =
+        DiagnosticId::from_u128(124111468001364594467257169111318924941);
+    /// GPU time of the JFA initialization pass in milliseconds.
+    pub const JFA_INIT_MS: DiagnosticId =
+        DiagnosticId::from_u128(325256639203132552706694761509269594766);
+    /// GPU time of all jump flood passes in milliseconds.
+    pub const JFA_MS: DiagnosticId =
+        DiagnosticId::from_u128(86821062403435297794593738313777082182);
+    /// GPU time of the composite pass in milliseconds.
+    pub const COMPOSITE_MS: DiagnosticId =
+        DiagnosticId::from_u128(309168098742827875151676089064828806962);
+    /// Number of mask passes encoded.
+    pub const MASK_PASSES: DiagnosticId =
+        DiagnosticId::from_u128(203762683937356205884344073778952468257);
+    /// Number of JFA initialization passes encoded.
+    pub const JFA_INIT_PASSES: DiagnosticId =
+        DiagnosticId::from_u128(109982694876073614880330759178088487171);
+    /// Number of jump flood passes encoded.
+    pub const JFA_PASSES: DiagnosticId =
+        DiagnosticId::from_u128(98246485072781893335785562879420840108);
+    /// Number of composite passes encoded.
+    pub const COMPOSITE_PASSES: DiagnosticId =
+        DiagnosticId::from_u128(165090000296989272962101991548049456133);
+    /// Number of draw calls issued by the mask passes, after instanced
+    /// batching.
+    pub const MASK_DRAWS: DiagnosticId =
+        DiagnosticId::from_u128(251749206381624370948213260675314968219);
+
+    const STAGE_MS: [DiagnosticId; 4] = [
+        Self::MASK_MS,
+        Self::JFA_INIT_MS,
+        Self::JFA_MS,
+        Self::COMPOSITE_MS,
+    ];
+    const STAGE_PASSES: [DiagnosticId; 4] = [
+        Self::MASK_PASSES,
+        Self::JFA_INIT_PASSES,
+        Self::JFA_PASSES,
+        Self::COMPOSITE_PASSES,
+    ];
+
+    fn diagnostic_system(mut diagnostics: Diagnostics, stats: Res<OutlineStats>) {
+        let outlined = stats.0.outlined_views.swap(0, Ordering::Relaxed);
+        let skipped = stats.0.skipped_views.swap(0, Ordering::Relaxed);
+
+        diagnostics.add_measurement(Self::OUTLINED_VIEWS, || outlined as f64);
+        diagnostics.add_measurement(Self::SKIPPED_VIEWS, || skipped as f64);
+
+        let mask_draws = stats.0.mask_draws.swap(0, Ordering::Relaxed);
+        diagnostics.add_measurement(Self::MASK_DRAWS, || mask_draws as f64);
+
+        for stage in OutlineStage::ALL {
+            let passes = stats.0.passes[stage as usize].swap(0, Ordering::Relaxed);
+            diagnostics.add_measurement(Self::STAGE_PASSES[stage as usize], || passes as f64);
+        }
+
+        if let Some(timings) = stats.0.timings.lock().unwrap().take() {
+            for stage in OutlineStage::ALL {
+                let ms = timings[stage as usize];
+                diagnostics.add_measurement(Self::STAGE_MS[stage as usize], || ms);
+            }
+        }
+    }
+}
+
+impl Plugin for OutlineDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_diagnostic(
+            Diagnostic::new(Self::OUTLINED_VIEWS, "bevy_jfa::outlined_views", 20)
+                .with_smoothing_factor(0.0),
+        )
+        .register_diagnostic(
+            Diagnostic::new(Self::SKIPPED_VIEWS, "bevy_jfa::skipped_views", 20)
+                .with_smoothing_factor(0.0),
+        )
+        .register_diagnostic(
+            Diagnostic::new(Self::MASK_MS, "bevy_jfa::mask_ms", 20).with_suffix("ms"),
+        )
+        .register_diagnostic(
+            Diagnostic::new(Self::JFA_INIT_MS, "bevy_jfa::jfa_init_ms", 20).with_suffix("ms"),
+        )
+        .register_diagnostic(
+            Diagnostic::new(Self::JFA_MS, "bevy_jfa::jfa_ms", 20).with_suffix("ms"),
+        )
+        .register_diagnostic(
+            Diagnostic::new(Self::COMPOSITE_MS, "bevy_jfa::composite_ms", 20).with_suffix("ms"),
+        )
+        .register_diagnostic(
+            Diagnostic::new(Self::MASK_PASSES, "bevy_jfa::mask_passes", 20)
+                .with_smoothing_factor(0.0),
+        )
+        .register_diagnostic(
+            Diagnostic::new(Self::JFA_INIT_PASSES, "bevy_jfa::jfa_init_passes", 20)
+                .with_smoothing_factor(0.0),
+        )
+        .register_diagnostic(
+            Diagnostic::new(Self::JFA_PASSES, "bevy_jfa::jfa_passes", 20)
+                .with_smoothing_factor(0.0),
+        )
+        .register_diagnostic(
+            Diagnostic::new(Self::COMPOSITE_PASSES, "bevy_jfa::composite_passes", 20)
+                .with_smoothing_factor(0.0),
+        )
+        .register_diagnostic(
+            Diagnostic::new(Self::MASK_DRAWS, "bevy_jfa::mask_draws", 20)
+                .with_smoothing_factor(0.0),
+        )
+        .add_systems(Update, Self::diagnostic_system);
+    }
+}
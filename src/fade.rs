@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::Outline;
+
+/// Component for fading an entity's outline in and out.
+///
+/// Without this component, an outline is shown at full intensity as soon as
+/// [`Outline::enabled`] is set, and hidden as soon as it is cleared. With it,
+/// the outline's intensity moves towards its target over `transition`, and the
+/// entity stays in the mask until it has faded out completely.
+///
+/// Outlines start fully faded out, so newly spawned outlines fade in.
+#[derive(Clone, Debug, PartialEq, Component, Reflect)]
+#[reflect(Component, Default)]
+pub struct OutlineIntensity {
+    /// Intensity of the outline while it is enabled, from 0 to 1.
+    pub intensity: f32,
+    /// Time taken to fade between no outline and full intensity.
+    pub transition: Duration,
+    pub(crate) current: f32,
+}
+
+impl OutlineIntensity {
+    pub fn new(intensity: f32, transition: Duration) -> OutlineIntensity {
+        OutlineIntensity {
+            intensity,
+            transition,
+            current: 0.0,
+        }
+    }
+
+    /// Returns the intensity the outline is currently drawn with.
+    pub fn current(&self) -> f32 {
+        self.current
+    }
+
+    /// Returns the current intensity of an outline with the given settings.
+    pub(crate) fn of(outline: &Outline, intensity: Option<&OutlineIntensity>) -> f32 {
+        match intensity {
+            Some(intensity) => intensity.current,
+            None if outline.enabled => 1.0,
+            None => 0.0,
+        }
+    }
+}
+
+impl Default for OutlineIntensity {
+    fn default() -> Self {
+        OutlineIntensity::new(1.0, Duration::from_millis(200))
+    }
+}
+
+pub(crate) fn animate_outline_intensity(
+    time: Res<Time>,
+    mut outlines: Query<(&Outline, &mut OutlineIntensity)>,
+) {
+    for (outline, mut intensity) in outlines.iter_mut() {
+        let target = match outline.enabled {
+            true => intensity.intensity.clamp(0.0, 1.0),
+            false => 0.0,
+        };
+        if intensity.current == target {
+            continue;
+        }
+
+        let transition = intensity.transition.as_secs_f32();
+        intensity.current = if transition > 0.0 {
+            let step = time.delta_seconds() / transition;
+            let delta = (target - intensity.current).clamp(-step, step);
+            intensity.current + delta
+        } else {
+            target
+        };
+    }
+}
@@ -3,15 +3,17 @@ use bevy::{
     render::{
         render_graph::{
             Node, NodeRunError, RenderGraph, RenderGraphContext, RenderGraphError, SlotInfo,
-            SlotType,
         },
-        render_resource::TextureFormat,
+        render_phase::RenderPhase,
         renderer::RenderContext,
-        texture::BevyDefault,
     },
 };
 
-use crate::{jfa::JfaNode, jfa_init::JfaInitNode, mask::MeshMaskNode, outline::OutlineNode};
+use crate::{
+    diagnostics::OutlineStats, jfa::JfaNode, jfa_init::JfaInitNode, mask::MeshMaskNode,
+    material::ViewOutlineComposite, outline::OutlineNode, resources::OutlineResources, MeshMask,
+    ViewOutlineBounds,
+};
 
 pub(crate) mod outline {
     pub const NAME: &str = "outline_graph";
@@ -35,9 +37,33 @@ impl Node for OutlineDriverNode {
         &self,
         graph: &mut RenderGraphContext,
         _render_context: &mut RenderContext,
-        _world: &World,
+        world: &World,
     ) -> Result<(), NodeRunError> {
         let view_ent = graph.view_entity();
+        let stats = world.resource::<OutlineStats>();
+
+        // Skip the mask, JFA and composite passes entirely if no outlined
+        // entities are visible in this view.
+        let has_masks = world
+            .get::<RenderPhase<MeshMask>>(view_ent)
+            .is_some_and(|phase| !phase.items.is_empty());
+        let offscreen = world
+            .get::<ViewOutlineBounds>(view_ent)
+            .is_some_and(|bounds| bounds.is_empty());
+        // The composite pipeline is missing while the outline material or
+        // style is loading, or if the style is missing from a loaded scene.
+        let has_composite = world.get::<ViewOutlineComposite>(view_ent).is_some();
+        // The render target may have no outline resources yet.
+        let has_target = world
+            .resource::<OutlineResources>()
+            .view_target(world, view_ent)
+            .is_some();
+        if !has_masks || offscreen || !has_composite || !has_target {
+            stats.view_skipped();
+            return Ok(());
+        }
+
+        stats.view_outlined();
         graph.run_sub_graph(outline::NAME, vec![], Some(view_ent))?;
 
         Ok(())
@@ -60,10 +86,7 @@ pub fn outline(render_app: &mut App) -> Result<RenderGraph, RenderGraphError> {
 
     let mask_node = MeshMaskNode::new(&mut render_app.world);
     let jfa_node = JfaNode::from_world(&mut render_app.world);
-    // TODO: BevyDefault for surface texture format is an anti-pattern;
-    // the target texture format should be queried from the window when
-    // Bevy exposes that functionality.
-    let outline_node = OutlineNode::new(&mut render_app.world, TextureFormat::bevy_default());
+    let outline_node = OutlineNode::new(&mut render_app.world);
 
     graph.add_node(outline::node::MASK_PASS, mask_node);
     graph.add_node(outline::node::JFA_INIT_PASS, JfaInitNode);
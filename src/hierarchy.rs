@@ -0,0 +1,414 @@
+use bevy::{
+    ecs::{
+        entity::{EntityMapper, MapEntities},
+        reflect::ReflectMapEntities,
+        system::SystemParam,
+    },
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    Outline, OutlineBlend, OutlineBlendMode, OutlineColor, OutlineGroup, OutlineIntensity,
+    OutlineOverrides, OutlineWidth,
+};
+
+/// Component for applying an entity's [`Outline`] to all of its descendants.
+///
+/// This is useful for outlining scenes, such as loaded glTF models, whose
+/// meshes are spawned as children of the entity carrying the `Outline`.
+/// Descendants with their own `Outline` component use that instead and pass
+/// it on to their own descendants; add `Outline { enabled: false }` to opt a
+/// subtree out.
+///
+/// Descendants inherit the root's [`OutlineGroup`], [`OutlineColor`],
+/// [`OutlineWidth`], [`OutlineBlend`] and the current intensity of its
+/// [`OutlineIntensity`]. If the root has no group,
+/// its descendants are still merged into a single silhouette. The same applies
+/// to the subtree of a descendant with its own `Outline`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Component, Reflect)]
+#[reflect(Component, Default)]
+pub struct OutlineHierarchy;
+
+/// The outline settings an entity inherits from an [`OutlineHierarchy`].
+///
+/// This is updated in `PostUpdate` for the hierarchies whose outlines or
+/// children changed, and should not be inserted manually.
+#[derive(Clone, Debug, PartialEq, Component, Reflect)]
+#[reflect(Component, Default, MapEntities)]
+pub struct InheritedOutline {
+    pub(crate) enabled: bool,
+    pub(crate) intensity: f32,
+    pub(crate) root: Entity,
+    pub(crate) group: Option<OutlineGroup>,
+    pub(crate) color: Option<Color>,
+    pub(crate) width: Option<f32>,
+    pub(crate) blend_mode: Option<OutlineBlendMode>,
+}
+
+impl Default for InheritedOutline {
+    fn default() -> Self {
+        InheritedOutline {
+            enabled: false,
+            intensity: 0.0,
+            root: Entity::PLACEHOLDER,
+            group: None,
+            color: None,
+            width: None,
+            blend_mode: None,
+        }
+    }
+}
+
+impl MapEntities for InheritedOutline {
+    fn map_entities(&mut self, entity_mapper: &mut EntityMapper) {
+        self.root = entity_mapper.get_or_reserve(self.root);
+    }
+}
+
+impl InheritedOutline {
+    /// Returns whether the inherited outline is enabled.
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns the current intensity of the inherited outline.
+    ///
+    /// See [`OutlineIntensity`].
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    /// Returns the inherited outline color, if overridden.
+    ///
+    /// See [`OutlineColor`].
+    pub fn color(&self) -> Option<Color> {
+        self.color
+    }
+
+    /// Returns the inherited outline width, if overridden.
+    ///
+    /// See [`OutlineWidth`].
+    pub fn width(&self) -> Option<f32> {
+        self.width
+    }
+
+    /// Returns the inherited outline blend mode, if overridden.
+    ///
+    /// See [`OutlineBlend`].
+    pub fn blend_mode(&self) -> Option<OutlineBlendMode> {
+        self.blend_mode
+    }
+
+    /// Returns the entity this outline is inherited from.
+    ///
+    /// This is the nearest ancestor with an [`Outline`], which is either the
+    /// [`OutlineHierarchy`] root or a descendant overriding its outline.
+    pub fn root(&self) -> Entity {
+        self.root
+    }
+
+    fn from_source(
+        source: Entity,
+        (outline, intensity, group, (color, width, blend)): OutlineSource,
+    ) -> Self {
+        InheritedOutline {
+            enabled: outline.enabled,
+            intensity: OutlineIntensity::of(outline, intensity),
+            root: source,
+            group: group.copied(),
+            color: color.map(|color| color.0),
+            width: width.map(|width| width.0),
+            blend_mode: blend.map(|blend| blend.0),
+        }
+    }
+}
+
+type OutlineSource<'a> = (
+    &'a Outline,
+    Option<&'a OutlineIntensity>,
+    Option<&'a OutlineGroup>,
+    (
+        Option<&'a OutlineColor>,
+        Option<&'a OutlineWidth>,
+        Option<&'a OutlineBlend>,
+    ),
+);
+
+type OutlineSourceQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Outline,
+        Option<&'static OutlineIntensity>,
+        Option<&'static OutlineGroup>,
+        OutlineOverrides,
+    ),
+>;
+
+type OutlineChangeQuery<'w, 's> = Query<
+    'w,
+    's,
+    Entity,
+    Or<(
+        Changed<Outline>,
+        Changed<OutlineIntensity>,
+        Changed<OutlineGroup>,
+        Changed<OutlineColor>,
+        Changed<OutlineWidth>,
+        Changed<OutlineBlend>,
+        Changed<OutlineHierarchy>,
+        Changed<Children>,
+        Changed<Parent>,
+    )>,
+>;
+
+/// Entities which lost a component affecting outline propagation since the
+/// last run.
+#[derive(SystemParam)]
+pub(crate) struct RemovedOutlineSources<'w, 's> {
+    outlines: RemovedComponents<'w, 's, Outline>,
+    intensities: RemovedComponents<'w, 's, OutlineIntensity>,
+    groups: RemovedComponents<'w, 's, OutlineGroup>,
+    colors: RemovedComponents<'w, 's, OutlineColor>,
+    widths: RemovedComponents<'w, 's, OutlineWidth>,
+    blends: RemovedComponents<'w, 's, OutlineBlend>,
+    hierarchies: RemovedComponents<'w, 's, OutlineHierarchy>,
+    children: RemovedComponents<'w, 's, Children>,
+}
+
+/// Returns the outermost [`OutlineHierarchy`] root among an entity and its
+/// ancestors.
+fn outermost_root(
+    entity: Entity,
+    roots: &Query<(), (With<Outline>, With<OutlineHierarchy>)>,
+    parents: &Query<&Parent>,
+) -> Option<Entity> {
+    let mut outermost = None;
+    let mut current = Some(entity);
+    while let Some(entity) = current {
+        if roots.contains(entity) {
+            outermost = Some(entity);
+        }
+        current = parents.get(entity).ok().map(Parent::get);
+    }
+    outermost
+}
+
+/// Updates the [`InheritedOutline`] of the descendants of every
+/// [`OutlineHierarchy`] root whose subtree changed since the last run.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn propagate_outline_hierarchy(
+    mut commands: Commands,
+    mut dirty_roots: Local<HashSet<Entity>>,
+    mut sources: Local<HashSet<Entity>>,
+    mut propagated: Local<HashMap<Entity, InheritedOutline>>,
+    mut stack: Local<Vec<(Entity, InheritedOutline)>>,
+    outlines: OutlineSourceQuery,
+    roots: Query<(), (With<Outline>, With<OutlineHierarchy>)>,
+    parents: Query<&Parent>,
+    children_query: Query<&Children>,
+    changed: OutlineChangeQuery,
+    mut removed: RemovedOutlineSources,
+    inherited: Query<(Entity, &InheritedOutline)>,
+) {
+    // Entities which lost their outline no longer provide one to the
+    // entities inheriting from them.
+    sources.clear();
+    sources.extend(removed.outlines.read());
+    sources.extend(removed.hierarchies.read());
+
+    dirty_roots.clear();
+    dirty_roots.extend(
+        changed
+            .iter()
+            .chain(sources.iter().copied())
+            .chain(removed.intensities.read())
+            .chain(removed.groups.read())
+            .chain(removed.colors.read())
+            .chain(removed.widths.read())
+            .chain(removed.blends.read())
+            .chain(removed.children.read())
+            .filter_map(|entity| outermost_root(entity, &roots, &parents)),
+    );
+    if dirty_roots.is_empty() && sources.is_empty() {
+        return;
+    }
+
+    propagated.clear();
+    for &root in dirty_roots.iter() {
+        let Ok(outline) = outlines.get(root) else {
+            continue;
+        };
+        sources.insert(root);
+        let inherited = InheritedOutline::from_source(root, outline);
+        stack.extend(
+            children_query
+                .get(root)
+                .into_iter()
+                .flatten()
+                .map(|&child| (child, inherited.clone())),
+        );
+
+        while let Some((entity, inherited)) = stack.pop() {
+            // Entities with their own outline pass it on to their subtree
+            // instead.
+            let inherited = match outlines.get(entity) {
+                Ok(outline) => {
+                    sources.insert(entity);
+                    InheritedOutline::from_source(entity, outline)
+                }
+                Err(_) => {
+                    propagated.insert(entity, inherited.clone());
+                    inherited
+                }
+            };
+            stack.extend(
+                children_query
+                    .get(entity)
+                    .into_iter()
+                    .flatten()
+                    .map(|&child| (child, inherited.clone())),
+            );
+        }
+    }
+
+    for (entity, current) in inherited.iter() {
+        match propagated.remove(&entity) {
+            Some(new) if new == *current => (),
+            Some(new) => {
+                commands.entity(entity).insert(new);
+            }
+            // Only entities whose source was revisited or removed have lost
+            // their inherited outline.
+            None if sources.contains(&current.root) => {
+                commands.entity(entity).remove::<InheritedOutline>();
+            }
+            None => (),
+        }
+    }
+
+    for (entity, new) in propagated.drain() {
+        if let Some(mut cmds) = commands.get_entity(entity) {
+            cmds.insert(new);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hierarchy_app() -> App {
+        let mut app = App::new();
+        app.add_systems(Update, propagate_outline_hierarchy);
+        app
+    }
+
+    fn inherited_root(app: &App, entity: Entity) -> Option<Entity> {
+        app.world
+            .get::<InheritedOutline>(entity)
+            .map(InheritedOutline::root)
+    }
+
+    #[test]
+    fn outline_propagates_to_descendants() {
+        let mut app = hierarchy_app();
+        let root = app
+            .world
+            .spawn((
+                Outline { enabled: true },
+                OutlineGroup(2),
+                OutlineColor(Color::RED),
+                OutlineWidth(12.0),
+                OutlineHierarchy,
+            ))
+            .id();
+        let child = app.world.spawn_empty().set_parent(root).id();
+        let grandchild = app.world.spawn_empty().set_parent(child).id();
+        app.update();
+
+        for entity in [child, grandchild] {
+            let inherited = app.world.get::<InheritedOutline>(entity).unwrap();
+            assert!(inherited.enabled());
+            assert_eq!(inherited.intensity(), 1.0);
+            assert_eq!(inherited.root(), root);
+            assert_eq!(inherited.group, Some(OutlineGroup(2)));
+            assert_eq!(inherited.color(), Some(Color::RED));
+            assert_eq!(inherited.width(), Some(12.0));
+        }
+        assert_eq!(inherited_root(&app, root), None);
+
+        // Children added later inherit the outline too.
+        let late = app.world.spawn_empty().set_parent(grandchild).id();
+        app.update();
+        assert_eq!(inherited_root(&app, late), Some(root));
+
+        app.world.get_mut::<Outline>(root).unwrap().enabled = false;
+        app.world
+            .entity_mut(root)
+            .remove::<OutlineColor>()
+            .insert(OutlineBlend(OutlineBlendMode::Additive));
+        app.update();
+        let inherited = app.world.get::<InheritedOutline>(late).unwrap();
+        assert!(!inherited.enabled());
+        assert_eq!(inherited.color(), None);
+        assert_eq!(inherited.blend_mode(), Some(OutlineBlendMode::Additive));
+    }
+
+    #[test]
+    fn own_outline_overrides_its_subtree() {
+        let mut app = hierarchy_app();
+        let root = app
+            .world
+            .spawn((Outline { enabled: true }, OutlineHierarchy))
+            .id();
+        let child = app
+            .world
+            .spawn((Outline { enabled: false }, OutlineGroup(5)))
+            .set_parent(root)
+            .id();
+        let grandchild = app.world.spawn_empty().set_parent(child).id();
+        app.update();
+
+        assert_eq!(inherited_root(&app, child), None);
+        let inherited = app.world.get::<InheritedOutline>(grandchild).unwrap();
+        assert!(!inherited.enabled());
+        assert_eq!(inherited.root(), child);
+        assert_eq!(inherited.group, Some(OutlineGroup(5)));
+
+        // Without its own outline, the subtree inherits the root's again.
+        app.world.entity_mut(child).remove::<Outline>();
+        app.update();
+        assert_eq!(inherited_root(&app, child), Some(root));
+        assert_eq!(inherited_root(&app, grandchild), Some(root));
+        assert!(app
+            .world
+            .get::<InheritedOutline>(grandchild)
+            .unwrap()
+            .enabled());
+    }
+
+    #[test]
+    fn inherited_outline_is_removed() {
+        let mut app = hierarchy_app();
+        let root = app
+            .world
+            .spawn((Outline { enabled: true }, OutlineHierarchy))
+            .id();
+        let moved = app.world.spawn_empty().set_parent(root).id();
+        let kept = app.world.spawn_empty().set_parent(root).id();
+        let other = app.world.spawn_empty().id();
+        app.update();
+        assert_eq!(inherited_root(&app, moved), Some(root));
+
+        app.world.entity_mut(moved).set_parent(other);
+        app.update();
+        assert_eq!(inherited_root(&app, moved), None);
+        assert_eq!(inherited_root(&app, kept), Some(root));
+
+        app.world.entity_mut(root).remove::<OutlineHierarchy>();
+        app.update();
+        assert_eq!(inherited_root(&app, kept), None);
+    }
+}
@@ -1,33 +1,71 @@
 use bevy::{
     prelude::*,
     render::{
-        render_asset::RenderAssets,
         render_graph::{Node, NodeRunError, RenderGraphContext, SlotInfo, SlotType},
         render_resource::{
             BindGroup, CachedRenderPipelineId, ColorTargetState, ColorWrites, FragmentState,
-            LoadOp, MultisampleState, Operations, PipelineCache, RenderPassColorAttachment,
-            RenderPassDescriptor, RenderPipelineDescriptor, ShaderType, TextureView, VertexState,
+            LoadOp, MultisampleState, Operations, PipelineCache, PushConstantRange,
+            RenderPassColorAttachment, RenderPassDescriptor, RenderPipelineDescriptor,
+            ShaderStages, ShaderType, TextureView, VertexState, WgpuFeatures,
         },
-        renderer::RenderContext,
+        renderer::{RenderContext, RenderDevice},
     },
 };
 
 use crate::{
-    resources::OutlineResources, CameraOutline, OutlineStyle, FULLSCREEN_PRIMITIVE_STATE,
-    JFA_SHADER_HANDLE, JFA_TEXTURE_FORMAT,
+    diagnostics::{OutlineStage, OutlineStats},
+    material::ViewOutlineComposite,
+    resources::{OutlineResources, ViewOutlineDimensions},
+    OutlineSettings, ViewOutlineBounds, FULLSCREEN_PRIMITIVE_STATE, JFA_SHADER_HANDLE,
 };
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, ShaderType)]
-pub struct JumpDist {
-    pub dist: u32,
+/// Size in bytes of the jump distance push constant.
+const JUMP_DIST_SIZE: u32 = std::mem::size_of::<u32>() as u32;
+
+/// The sequence of jump distances used by the jump flood passes.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Reflect)]
+pub enum JumpSchedule {
+    /// Standard JFA: power-of-two jumps, halving from the outline width down
+    /// to a single pixel.
+    #[default]
+    Standard,
+    /// 1+JFA: a jump of 1 followed by the standard schedule.
+    OnePlus,
+    /// JFA+2: the standard schedule followed by jumps of 2 and 1, which
+    /// corrects most of the errors left by standard JFA.
+    PlusTwo,
+    /// An explicit list of jump distances in pixels, applied in order.
+    ///
+    /// An empty list is treated as [`JumpSchedule::Standard`].
+    Custom(Vec<u32>),
+}
+
+impl JumpSchedule {
+    /// Returns the jump distances used for an outline `width` pixels wide.
+    pub fn steps(&self, width: f32) -> Vec<u32> {
+        let max_exp = width.max(1.0).log2() as u32;
+        let standard = (0..=max_exp).rev().map(|exp| 1 << exp);
+
+        match self {
+            JumpSchedule::Standard => standard.collect(),
+            JumpSchedule::OnePlus => std::iter::once(1).chain(standard).collect(),
+            JumpSchedule::PlusTwo => standard.chain([2, 1]).collect(),
+            JumpSchedule::Custom(steps) if steps.is_empty() => standard.collect(),
+            JumpSchedule::Custom(steps) => steps.clone(),
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, ShaderType)]
 pub struct Dimensions {
-    width: f32,
-    height: f32,
+    pub(crate) width: f32,
+    pub(crate) height: f32,
     inv_width: f32,
     inv_height: f32,
+    // Viewport of the view in pixels, as `(x, y, width, height)`.
+    pub(crate) viewport: Vec4,
+    // Width of the view's outline in pixels.
+    outline_width: f32,
 }
 
 impl Dimensions {
@@ -37,6 +75,24 @@ impl Dimensions {
             height: height as f32,
             inv_width: 1.0 / width as f32,
             inv_height: 1.0 / height as f32,
+            viewport: Vec4::new(0.0, 0.0, width as f32, height as f32),
+            outline_width: 0.0,
+        }
+    }
+
+    /// Restricts the dimensions to a viewport within the framebuffer.
+    pub fn with_viewport(self, viewport: UVec4) -> Dimensions {
+        Dimensions {
+            viewport: viewport.as_vec4(),
+            ..self
+        }
+    }
+
+    /// Sets the width of the outline drawn in these dimensions.
+    pub fn with_outline_width(self, outline_width: f32) -> Dimensions {
+        Dimensions {
+            outline_width,
+            ..self
         }
     }
 }
@@ -44,6 +100,9 @@ impl Dimensions {
 #[derive(Resource)]
 pub struct JfaPipeline {
     cached: CachedRenderPipelineId,
+    // Whether jump distances are passed as push constants. Otherwise, they
+    // are passed as the instance index.
+    push_constants: bool,
 }
 
 impl FromWorld for JfaPipeline {
@@ -51,22 +110,40 @@ impl FromWorld for JfaPipeline {
         let res = world.get_resource::<OutlineResources>().unwrap();
         let dimensions_bind_group_layout = res.dimensions_bind_group_layout.clone();
         let jfa_bind_group_layout = res.jfa_bind_group_layout.clone();
+        let jfa_texture_format = res
+            .jfa_texture_format
+            .expect("JFA pipeline created without a JFA texture format");
+
+        let device = world.resource::<RenderDevice>();
+        let push_constants = device.features().contains(WgpuFeatures::PUSH_CONSTANTS)
+            && device.limits().max_push_constant_size >= JUMP_DIST_SIZE;
+
+        let mut shader_defs = vec![];
+        let mut push_constant_ranges = vec![];
+        if push_constants {
+            shader_defs.push("JFA_PUSH_CONSTANTS".into());
+            push_constant_ranges.push(PushConstantRange {
+                stages: ShaderStages::VERTEX,
+                range: 0..JUMP_DIST_SIZE,
+            });
+        }
+
         let pipeline_cache = world.get_resource_mut::<PipelineCache>().unwrap();
         let cached = pipeline_cache.queue_render_pipeline(RenderPipelineDescriptor {
             label: Some("outline_jfa_pipeline".into()),
             layout: vec![dimensions_bind_group_layout, jfa_bind_group_layout],
             vertex: VertexState {
-                shader: JFA_SHADER_HANDLE.typed::<Shader>(),
-                shader_defs: vec![],
+                shader: JFA_SHADER_HANDLE,
+                shader_defs: shader_defs.clone(),
                 entry_point: "vertex".into(),
                 buffers: vec![],
             },
             fragment: Some(FragmentState {
-                shader: JFA_SHADER_HANDLE.typed::<Shader>(),
-                shader_defs: vec![],
+                shader: JFA_SHADER_HANDLE,
+                shader_defs,
                 entry_point: "fragment".into(),
                 targets: vec![Some(ColorTargetState {
-                    format: JFA_TEXTURE_FORMAT,
+                    format: jfa_texture_format,
                     blend: None,
                     write_mask: ColorWrites::ALL,
                 })],
@@ -74,15 +151,21 @@ impl FromWorld for JfaPipeline {
             primitive: FULLSCREEN_PRIMITIVE_STATE,
             depth_stencil: None,
             multisample: MultisampleState::default(),
-            push_constant_ranges: vec![],
+            push_constant_ranges,
         });
 
-        JfaPipeline { cached }
+        JfaPipeline {
+            cached,
+            push_constants,
+        }
     }
 }
 
 pub struct JfaNode {
-    query: QueryState<&'static CameraOutline>,
+    query: QueryState<(
+        &'static ViewOutlineComposite,
+        &'static ViewOutlineDimensions,
+    )>,
 }
 
 impl FromWorld for JfaNode {
@@ -100,9 +183,7 @@ impl JfaNode {
 
 impl Node for JfaNode {
     fn input(&self) -> Vec<SlotInfo> {
-        vec![
-            SlotInfo::new(Self::IN_BASE, SlotType::TextureView),
-        ]
+        vec![SlotInfo::new(Self::IN_BASE, SlotType::TextureView)]
     }
 
     fn output(&self) -> Vec<SlotInfo> {
@@ -119,27 +200,29 @@ impl Node for JfaNode {
         render_context: &mut RenderContext,
         world: &World,
     ) -> Result<(), NodeRunError> {
+        let view_entity = graph.view_entity();
         let res = world.resource::<OutlineResources>();
+        let target_res = res.view_target(world, view_entity).unwrap();
         graph
-            .set_output(Self::OUT_JUMP, res.jfa_final_output.default_view.clone())
+            .set_output(
+                Self::OUT_JUMP,
+                target_res.jfa_final_output.default_view.clone(),
+            )
             .unwrap();
 
-        let view_entity = graph.view_entity();
-        let styles = world.resource::<RenderAssets<OutlineStyle>>();
-        let width = match self
-            .query
-            .get_manual(world, view_entity)
-        {
-            Ok(outline) => {
-                let dims = res.dimensions_buffer.get();
-                dims.width
-                    .max(dims.height)
-                    .min(styles.get(&outline.style).unwrap().params.weight.ceil())
+        let (width, dims_offset) = match self.query.get_manual(world, view_entity) {
+            Ok((composite, dims)) => {
+                let viewport = dims.dimensions.viewport;
+                let width = viewport.z.max(viewport.w).min(composite.max_width.ceil());
+                (width, dims.offset)
             }
             Err(_) => return Ok(()),
         };
 
-        let pipeline = world.get_resource::<JfaPipeline>().unwrap();
+        // The pipeline isn't created without a JFA texture format.
+        let Some(pipeline) = world.get_resource::<JfaPipeline>() else {
+            return Ok(());
+        };
         let pipeline_cache = world.get_resource::<PipelineCache>().unwrap();
         let cached_pipeline = match pipeline_cache.get_render_pipeline(pipeline.cached) {
             Some(c) => c,
@@ -149,35 +232,34 @@ impl Node for JfaNode {
             }
         };
 
-        // The half-width of the JFA region is 2^(max_exp + 1) - 1.
-        //
-        // weight < 2^(max_exp + 1) - 1
-        // weight + 1 < 2^(max_exp + 1)
-        // log2(weight + 1) < max_exp + 1
-        // max_exp > log2(weight + 1) - 1
+        let bounds = world.get::<ViewOutlineBounds>(view_entity);
 
-        let max_exp = width.log2() as usize;
-        //let max_exp = width.log2().ceil() as usize;
-        for it in 0..=max_exp {
-            let exp = max_exp - it;
+        let steps = world
+            .resource::<OutlineSettings>()
+            .jump_schedule
+            .steps(width);
+        let last = steps.len() - 1;
 
+        let stats = world.resource::<OutlineStats>();
+        stats.begin_stage(OutlineStage::Jfa, render_context);
+        for (it, &dist) in steps.iter().enumerate() {
             let target: &TextureView;
             let src: &BindGroup;
 
             if it % 2 == 1 {
-                if it == max_exp {
-                    target = &res.jfa_final_output.default_view;
+                if it == last {
+                    target = &target_res.jfa_final_output.default_view;
                 } else {
-                    target = &res.jfa_primary_output.default_view;
+                    target = &target_res.jfa_primary_output.default_view;
                 }
-                src = &res.jfa_from_secondary_bind_group;
+                src = &target_res.jfa_from_secondary_bind_group;
             } else {
-                if it == max_exp {
-                    target = &res.jfa_final_output.default_view;
+                if it == last {
+                    target = &target_res.jfa_final_output.default_view;
                 } else {
-                    target = &res.jfa_secondary_output.default_view;
+                    target = &target_res.jfa_secondary_output.default_view;
                 }
-                src = &res.jfa_from_primary_bind_group;
+                src = &target_res.jfa_from_primary_bind_group;
             }
 
             let attachment = RenderPassColorAttachment {
@@ -197,17 +279,32 @@ impl Node for JfaNode {
                     store: true,
                 },
             };
+            stats.begin_scope(&format!("outline_jfa_{dist}"), render_context);
             let mut tracked_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
                 label: Some("outline_jfa"),
                 color_attachments: &[Some(attachment)],
                 depth_stencil_attachment: None,
             });
+            if let Some(bounds) = bounds {
+                bounds.set_scissor(&mut tracked_pass);
+            }
             tracked_pass.set_render_pipeline(cached_pipeline);
-            tracked_pass.set_bind_group(0, &res.dimensions_bind_group, &[]);
-            tracked_pass.set_bind_group(1, src, &[res.jfa_distance_offsets[exp]]);
-            tracked_pass.draw(0..3, 0..1);
+            tracked_pass.set_bind_group(0, &res.dimensions_bind_group, &[dims_offset]);
+            tracked_pass.set_bind_group(1, src, &[]);
+            if pipeline.push_constants {
+                tracked_pass.set_push_constants(ShaderStages::VERTEX, 0, &dist.to_le_bytes());
+                tracked_pass.draw(0..3, 0..1);
+            } else {
+                tracked_pass.draw(0..3, dist..dist + 1);
+            }
+            drop(tracked_pass);
+
+            stats.pass_recorded(OutlineStage::Jfa);
+            stats.end_scope(render_context);
         }
 
+        stats.end_stage(render_context);
+
         Ok(())
     }
 }
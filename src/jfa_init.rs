@@ -3,20 +3,48 @@ use bevy::{
     render::{
         render_graph::{Node, NodeRunError, RenderGraphContext, SlotInfo, SlotType},
         render_resource::{
-            CachedRenderPipelineId, ColorTargetState, ColorWrites, Face, FragmentState, FrontFace,
-            LoadOp, MultisampleState, Operations, PipelineCache, PolygonMode, PrimitiveState,
-            PrimitiveTopology, RenderPassColorAttachment, RenderPassDescriptor,
-            RenderPipelineDescriptor, VertexState,
+            BindGroupLayout, CachedRenderPipelineId, ColorTargetState, ColorWrites,
+            CompareFunction, DepthStencilState, Face, FragmentState, FrontFace, LoadOp,
+            MultisampleState, Operations, PipelineCache, PolygonMode, PrimitiveState,
+            PrimitiveTopology, RenderPassColorAttachment, RenderPassDepthStencilAttachment,
+            RenderPassDescriptor, RenderPipelineDescriptor, ShaderDefVal, StencilFaceState,
+            StencilOperation, StencilState, TextureFormat, VertexState,
         },
         renderer::RenderContext,
+        view::ViewUniformOffset,
     },
 };
 
-use crate::{resources::OutlineResources, JFA_INIT_SHADER_HANDLE, JFA_TEXTURE_FORMAT};
+use crate::{
+    diagnostics::{OutlineStage, OutlineStats},
+    mask::{MASK_STENCIL_REFERENCE, MASK_STENCIL_TEXTURE_FORMAT},
+    resources::{OutlineResources, ViewOutlineDimensions, ViewPrepassMask},
+    ViewOutlineBounds, JFA_INIT_SHADER_HANDLE,
+};
 
 #[derive(Resource)]
 pub struct JfaInitPipeline {
-    cached: CachedRenderPipelineId,
+    multisampled: CachedRenderPipelineId,
+    single_sample: CachedRenderPipelineId,
+    stencil: CachedRenderPipelineId,
+    prepass: CachedRenderPipelineId,
+    prepass_multisampled: CachedRenderPipelineId,
+}
+
+impl JfaInitPipeline {
+    /// Returns the pipeline matching the kind and sample count of the mask.
+    ///
+    /// Views with a prepass mask also read their normal prepass, which is
+    /// multisampled like the view.
+    fn cached(&self, res: &OutlineResources, prepass: bool, msaa: &Msaa) -> CachedRenderPipelineId {
+        match (res.stencil_mask, prepass, res.mask_sample_count) {
+            (true, _, _) => self.stencil,
+            (false, true, _) if msaa.samples() > 1 => self.prepass_multisampled,
+            (false, true, _) => self.prepass,
+            (false, false, 1) => self.single_sample,
+            (false, false, _) => self.multisampled,
+        }
+    }
 }
 
 impl FromWorld for JfaInitPipeline {
@@ -24,62 +52,159 @@ impl FromWorld for JfaInitPipeline {
         let res = world.resource::<OutlineResources>();
         let dims_layout = res.dimensions_bind_group_layout.clone();
         let init_layout = res.jfa_init_bind_group_layout.clone();
+        let single_sample_init_layout = res.jfa_init_single_sample_bind_group_layout.clone();
+        let prepass_layout = res.jfa_init_prepass_bind_group_layout.clone();
+        let single_sample_prepass_layout =
+            res.jfa_init_single_sample_prepass_bind_group_layout.clone();
+        let format = res
+            .jfa_texture_format
+            .expect("JFA init pipeline created without a JFA texture format");
 
         let pipeline_cache = world.get_resource_mut::<PipelineCache>().unwrap();
-        let cached = pipeline_cache.queue_render_pipeline(RenderPipelineDescriptor {
-            label: Some("outline_jfa_init_pipeline".into()),
-            layout: vec![dims_layout, init_layout],
-            vertex: VertexState {
-                shader: JFA_INIT_SHADER_HANDLE.typed::<Shader>(),
-                shader_defs: vec![],
-                entry_point: "vertex".into(),
-                buffers: vec![],
-            },
-            primitive: PrimitiveState {
-                topology: PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: FrontFace::Ccw,
-                cull_mode: Some(Face::Back),
-                unclipped_depth: false,
-                polygon_mode: PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: MultisampleState::default(),
-            fragment: Some(FragmentState {
-                shader: JFA_INIT_SHADER_HANDLE.typed::<Shader>(),
-                shader_defs: vec![],
-                entry_point: "fragment".into(),
-                targets: vec![Some(ColorTargetState {
-                    format: JFA_TEXTURE_FORMAT,
-                    blend: None,
-                    write_mask: ColorWrites::ALL,
-                })],
+        let multisampled = queue_jfa_init_pipeline(
+            &pipeline_cache,
+            vec![dims_layout.clone(), init_layout],
+            vec!["MASK_MULTISAMPLED".into()],
+            format,
+            None,
+        );
+        let single_sample = queue_jfa_init_pipeline(
+            &pipeline_cache,
+            vec![dims_layout.clone(), single_sample_init_layout.clone()],
+            vec![],
+            format,
+            None,
+        );
+        // Prepass masks are single-sampled, but the normal prepass has the
+        // sample count of the view.
+        let prepass = queue_jfa_init_pipeline(
+            &pipeline_cache,
+            vec![
+                dims_layout.clone(),
+                single_sample_init_layout.clone(),
+                single_sample_prepass_layout,
+            ],
+            vec!["PREPASS_MASK".into()],
+            format,
+            None,
+        );
+        let prepass_multisampled = queue_jfa_init_pipeline(
+            &pipeline_cache,
+            vec![
+                dims_layout.clone(),
+                single_sample_init_layout.clone(),
+                prepass_layout,
+            ],
+            vec!["PREPASS_MASK".into(), "PREPASS_MULTISAMPLED".into()],
+            format,
+            None,
+        );
+        // Only pixels covered by the stencil mask can be seeds, so the rest
+        // are rejected before shading. Interior pixels still pass the test,
+        // and the shader writes no seed for them as no neighbor differs.
+        let stencil_face = StencilFaceState {
+            compare: CompareFunction::Equal,
+            fail_op: StencilOperation::Keep,
+            depth_fail_op: StencilOperation::Keep,
+            pass_op: StencilOperation::Keep,
+        };
+        let stencil = queue_jfa_init_pipeline(
+            &pipeline_cache,
+            vec![dims_layout, single_sample_init_layout],
+            vec![],
+            format,
+            Some(DepthStencilState {
+                format: MASK_STENCIL_TEXTURE_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: CompareFunction::Always,
+                stencil: StencilState {
+                    front: stencil_face,
+                    back: stencil_face,
+                    read_mask: 0xff,
+                    write_mask: 0,
+                },
+                bias: default(),
             }),
-            push_constant_ranges: vec![],
-        });
+        );
 
-        JfaInitPipeline { cached }
+        JfaInitPipeline {
+            multisampled,
+            single_sample,
+            stencil,
+            prepass,
+            prepass_multisampled,
+        }
     }
 }
 
+fn queue_jfa_init_pipeline(
+    pipeline_cache: &PipelineCache,
+    layout: Vec<BindGroupLayout>,
+    shader_defs: Vec<ShaderDefVal>,
+    format: TextureFormat,
+    depth_stencil: Option<DepthStencilState>,
+) -> CachedRenderPipelineId {
+    pipeline_cache.queue_render_pipeline(RenderPipelineDescriptor {
+        label: Some("outline_jfa_init_pipeline".into()),
+        layout,
+        vertex: VertexState {
+            shader: JFA_INIT_SHADER_HANDLE,
+            shader_defs: vec![],
+            entry_point: "vertex".into(),
+            buffers: vec![],
+        },
+        primitive: PrimitiveState {
+            topology: PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: FrontFace::Ccw,
+            cull_mode: Some(Face::Back),
+            unclipped_depth: false,
+            polygon_mode: PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil,
+        multisample: MultisampleState::default(),
+        fragment: Some(FragmentState {
+            shader: JFA_INIT_SHADER_HANDLE,
+            shader_defs,
+            entry_point: "fragment".into(),
+            targets: vec![Some(ColorTargetState {
+                format,
+                blend: None,
+                write_mask: ColorWrites::ALL,
+            })],
+        }),
+        push_constant_ranges: vec![],
+    })
+}
+
 /// Render graph node for the JFA initialization pass.
 pub struct JfaInitNode;
 
 impl JfaInitNode {
-    /// The input stencil buffer.
+    /// The input object ID buffer.
     ///
-    /// This should have the format `TextureFormat::Depth24PlusStencil8`.
-    /// Fragments in the JFA initialization pass will pass the stencil test if
-    /// the corresponding stencil buffer value is 255, and fail otherwise.
-    /// The depth aspect is ignored.
-    pub const IN_MASK: &'static str = "in_stencil";
+    /// This should be a texture with the format
+    /// `bevy_jfa::mask::MASK_ID_TEXTURE_FORMAT`, where 0 marks the background,
+    /// multisampled unless the mask sample count is 1. Only the first sample
+    /// of each pixel is read.
+    ///
+    /// In stencil mode, this is a texture with the format
+    /// `bevy_jfa::mask::MASK_STENCIL_TEXTURE_FORMAT`, which is also attached
+    /// for the stencil test.
+    pub const IN_MASK: &'static str = "in_mask_id";
 
     /// The produced initialized JFA buffer.
     ///
-    /// This has the format `bevy_jfa::JFA_TEXTURE_FORMAT`. Fragments that pass
-    /// the stencil test are assigned their framebuffer coordinates. Fragments
-    /// that fail the stencil test are assigned a value of (-1, -1).
+    /// This has the format `OutlineResources::jfa_texture_format`, which is
+    /// `Rg16Snorm` where supported. Fragments whose
+    /// object ID differs from that of a neighboring fragment are assigned
+    /// framebuffer coordinates on the edge of the mask, estimated with
+    /// sub-pixel precision from the resolved mask coverage. All other
+    /// fragments are assigned a value of (-1, -1). In stencil mode, only
+    /// fragments covered by outlined meshes are shaded, so only they can be
+    /// seeds. For views with a prepass mask, seeds inside the mask are moved
+    /// along the normal prepass where it faces away from the mask.
     pub const OUT_JFA_INIT: &'static str = "out_jfa_init";
 }
 
@@ -99,16 +224,30 @@ impl Node for JfaInitNode {
         world: &World,
     ) -> Result<(), NodeRunError> {
         let res = world.get_resource::<OutlineResources>().unwrap();
+        let target_res = res.view_target(world, graph.view_entity()).unwrap();
         graph
             .set_output(
                 Self::OUT_JFA_INIT,
-                res.jfa_primary_output.default_view.clone(),
+                target_res.jfa_primary_output.default_view.clone(),
             )
             .unwrap();
 
-        let pipeline = world.get_resource::<JfaInitPipeline>().unwrap();
+        let Some(dims) = world.get::<ViewOutlineDimensions>(graph.view_entity()) else {
+            return Ok(());
+        };
+
+        // The pipeline isn't created without a JFA texture format.
+        let Some(pipeline) = world.get_resource::<JfaInitPipeline>() else {
+            return Ok(());
+        };
         let pipeline_cache = world.get_resource::<PipelineCache>().unwrap();
-        let cached_pipeline = match pipeline_cache.get_render_pipeline(pipeline.cached) {
+        let prepass_mask = world.get::<ViewPrepassMask>(graph.view_entity());
+        let msaa = world.resource::<Msaa>();
+        let cached_pipeline = match pipeline_cache.get_render_pipeline(pipeline.cached(
+            res,
+            prepass_mask.is_some(),
+            msaa,
+        )) {
             Some(c) => c,
             // Still queued.
             None => {
@@ -116,10 +255,13 @@ impl Node for JfaInitNode {
             }
         };
 
+        let stats = world.resource::<OutlineStats>();
+        stats.begin_stage(OutlineStage::JfaInit, render_context);
+
         let mut tracked_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
             label: Some("outline_jfa_init"),
             color_attachments: &[Some(RenderPassColorAttachment {
-                view: &res.jfa_primary_output.default_view,
+                view: &target_res.jfa_primary_output.default_view,
                 resolve_target: None,
                 ops: Operations {
                     load: LoadOp::Clear(
@@ -134,12 +276,31 @@ impl Node for JfaInitNode {
                     store: true,
                 },
             })],
-            depth_stencil_attachment: None,
+            // Attached read-only, as the stencil is also sampled.
+            depth_stencil_attachment: res.stencil_mask.then(|| RenderPassDepthStencilAttachment {
+                view: &target_res.mask_depth_multisample.default_view,
+                depth_ops: None,
+                stencil_ops: None,
+            }),
         });
+        if res.stencil_mask {
+            tracked_pass.set_stencil_reference(MASK_STENCIL_REFERENCE);
+        }
+        if let Some(bounds) = world.get::<ViewOutlineBounds>(graph.view_entity()) {
+            bounds.set_scissor(&mut tracked_pass);
+        }
         tracked_pass.set_render_pipeline(cached_pipeline);
-        tracked_pass.set_bind_group(0, &res.dimensions_bind_group, &[]);
-        tracked_pass.set_bind_group(1, &res.jfa_init_bind_group, &[]);
+        tracked_pass.set_bind_group(0, &res.dimensions_bind_group, &[dims.offset]);
+        tracked_pass.set_bind_group(1, &target_res.jfa_init_bind_group, &[]);
+        if let Some(prepass_mask) = prepass_mask {
+            let view_uniform = world.get::<ViewUniformOffset>(graph.view_entity()).unwrap();
+            tracked_pass.set_bind_group(2, &prepass_mask.bind_group, &[view_uniform.offset]);
+        }
         tracked_pass.draw(0..3, 0..1);
+        drop(tracked_pass);
+
+        stats.pass_recorded(OutlineStage::JfaInit);
+        stats.end_stage(render_context);
 
         Ok(())
     }
@@ -1,5 +1,3 @@
-#![feature(const_type_id)]
-
 //! A Bevy library for computing the Jump Flooding Algorithm.
 //!
 //! The **jump flooding algorithm** (JFA) is a fast screen-space algorithm for
@@ -20,38 +18,128 @@
 //!    camera which should render the outline.  Currently, outline styling is
 //!    tied to the camera rather than the mesh.
 //! 4. Add an [`Outline`] component to the mesh with `enabled: true`.
+//!
+//! The color, width and blend mode of a mesh's outline can be overridden with
+//! an [`OutlineColor`], [`OutlineWidth`] and [`OutlineBlend`].
+//!
+//! To composite outlines with a custom shader, implement [`OutlineMaterial`]
+//! and add its [`OutlineMaterialPlugin`].
+//!
+//! Meshes whose material deforms their vertices can be drawn into the outline
+//! mask with a matching vertex shader through [`OutlineMaskMaterial`].
+//!
+//! To outline every mesh in an entity hierarchy, such as a spawned scene, add
+//! [`OutlineHierarchy`] alongside the `Outline` on the hierarchy's root.
+//!
+//! To outline meshes under the cursor or after they are clicked, add the
+//! [`OutlinePickingPlugin`] and an [`OutlineOnHover`] or [`OutlineOnSelect`]
+//! component to the mesh.
+//!
+//! The [`cpu`] module contains a reference implementation of the jump flood
+//! passes for testing without a GPU.
 
-use std::{any::TypeId, ops::Range};
+use std::{
+    any::TypeId,
+    cmp::{Ordering, Reverse},
+    ops::Range,
+};
+
+use serde::{Deserialize, Serialize};
 
 use bevy::{
-    app::prelude::*, asset::{Asset, AssetApp, AssetId, Assets, Handle, UntypedAssetId, UntypedHandle}, core_pipeline::core_3d, ecs::{prelude::*, query::QueryItem, system::{lifetimeless::SRes, SystemParamItem}}, math::Mat4, pbr::{DrawMesh, MaterialBindGroupId, Mesh3d, MeshPipelineKey, MeshTransforms, MeshUniform, RenderMeshInstances, SetMeshBindGroup, SetMeshViewBindGroup}, prelude::Camera3d, reflect::{TypePath, TypeUuid}, render::{
-        batching::{batch_and_prepare_render_phase, GetBatchData}, extract_resource::ExtractResource, prelude::*, render_asset::{PrepareAssetError, RenderAsset, RenderAssetPlugin, RenderAssets}, render_graph::RenderGraph, render_phase::{
-            AddRenderCommand, CachedRenderPipelinePhaseItem, DrawFunctionId, DrawFunctions,
-            PhaseItem, RenderPhase, SetItemPipeline,
-        }, render_resource::*, renderer::{RenderDevice, RenderQueue}, view::{ExtractedView, VisibleEntities}, Extract, Render, RenderApp, RenderSet
-    }, transform::components::GlobalTransform, utils::{nonmax::NonMaxU32, FloatOrd, Uuid}
+    app::prelude::*,
+    asset::{Asset, AssetApp, AssetId, AssetIndex, Assets, Handle, UntypedAssetId},
+    core_pipeline::core_3d,
+    ecs::{
+        prelude::*,
+        query::QueryItem,
+        system::{
+            lifetimeless::{Read, SRes},
+            SystemParamItem,
+        },
+    },
+    math::Mat4,
+    pbr::{DrawMesh, Mesh3d, MeshPipelineKey, RenderMeshInstances, SetMeshViewBindGroup},
+    prelude::Camera3d,
+    reflect::{std_traits::ReflectDefault, Reflect, ReflectDeserialize, ReflectSerialize},
+    render::{
+        batching::{batch_and_prepare_render_phase, write_batched_instance_buffer, GetBatchData},
+        extract_resource::ExtractResource,
+        prelude::*,
+        primitives::Aabb,
+        render_asset::RenderAssets,
+        render_graph::RenderGraph,
+        render_phase::{
+            sort_phase_system, AddRenderCommand, CachedRenderPipelinePhaseItem, DrawFunctionId,
+            DrawFunctions, PhaseItem, RenderPhase, SetItemPipeline,
+        },
+        render_resource::*,
+        renderer::RenderDevice,
+        view::{ExtractedView, RenderLayers, VisibleEntities},
+        Extract, Render, RenderApp, RenderSet,
+    },
+    transform::components::GlobalTransform,
+    utils::{nonmax::NonMaxU32, FloatOrd, HashMap, Uuid},
+};
+
+pub use crate::{
+    bounds::ViewOutlineBounds,
+    diagnostics::OutlineDiagnosticsPlugin,
+    fade::OutlineIntensity,
+    hierarchy::{InheritedOutline, OutlineHierarchy},
+    jfa::JumpSchedule,
+    loader::{OutlineStyleLoader, OutlineStyleLoaderError},
+    mask_material::{OutlineMaskMaterial, OutlineMaskMaterialPlugin},
+    material::{OutlineMaterial, OutlineMaterialPlugin},
+    outline::{OutlineBlendMode, OutlineDebugMode},
+    picking::{
+        OutlineHovered, OutlineOnHover, OutlineOnSelect, OutlinePickingPlugin,
+        OutlinePickingSettings, OutlineSelected,
+    },
 };
 
 use crate::{
     graph::OutlineDriverNode,
-    mask::MeshMaskPipeline,
-    outline::{GpuOutlineParams, OutlineParams},
+    mask::{
+        MeshMaskMaterials, MeshMaskPipeline, MeshMaskPipelineKey, MeshMaskUniform,
+        SetMeshMaskBindGroup, ViewPrepasses,
+    },
+    material::{CustomOutlineMaterial, ViewOutlineMaterial},
+    outline::OutlineParams,
     resources::OutlineResources,
 };
 
+mod bounds;
+pub mod cpu;
+mod diagnostics;
+mod fade;
 mod graph;
+mod hierarchy;
 mod jfa;
 mod jfa_init;
+mod loader;
 mod mask;
+mod mask_material;
+mod material;
 mod outline;
+mod picking;
 mod resources;
 
 #[derive(Component)]
 pub struct ExtractedOutline {
     mesh: Handle<Mesh>,
     transform: Mat4,
+    layers: RenderLayers,
+    aabb: Option<Aabb>,
+    id: u32,
+    intensity: f32,
+    color: Option<Color>,
+    width: Option<f32>,
+    blend_mode: Option<OutlineBlendMode>,
 }
 
+/// Preferred format of the JFA textures. See `resources::jfa_texture_format`
+/// for the fallbacks.
 const JFA_TEXTURE_FORMAT: TextureFormat = TextureFormat::Rg16Snorm;
 const FULLSCREEN_PRIMITIVE_STATE: PrimitiveState = PrimitiveState {
     topology: PrimitiveTopology::TriangleList,
@@ -68,9 +156,15 @@ const FULLSCREEN_PRIMITIVE_STATE: PrimitiveState = PrimitiveState {
 pub struct OutlinePlugin;
 
 /// Performance and visual quality settings for JFA-based outlines.
-#[derive(Clone, ExtractResource, Resource)]
+#[derive(Clone, ExtractResource, Resource, Reflect)]
+#[reflect(Resource, Default)]
 pub struct OutlineSettings {
     pub(crate) half_resolution: bool,
+    pub(crate) jump_schedule: JumpSchedule,
+    pub(crate) debug_mode: Option<OutlineDebugMode>,
+    pub(crate) mask_sample_count: Option<u32>,
+    pub(crate) stencil_mask: bool,
+    pub(crate) prepass_mask: bool,
 }
 
 impl OutlineSettings {
@@ -83,66 +177,182 @@ impl OutlineSettings {
     pub fn set_half_resolution(&mut self, value: bool) {
         self.half_resolution = value;
     }
+
+    /// Returns the sequence of jump distances used by the jump flood passes.
+    pub fn jump_schedule(&self) -> &JumpSchedule {
+        &self.jump_schedule
+    }
+
+    /// Sets the sequence of jump distances used by the jump flood passes.
+    pub fn set_jump_schedule(&mut self, value: JumpSchedule) {
+        self.jump_schedule = value;
+    }
+
+    /// Returns the debug visualization replacing the outline, if any.
+    pub fn debug_mode(&self) -> Option<OutlineDebugMode> {
+        self.debug_mode
+    }
+
+    /// Sets the debug visualization replacing the outline.
+    pub fn set_debug_mode(&mut self, value: Option<OutlineDebugMode>) {
+        self.debug_mode = value;
+    }
+
+    /// Returns the requested sample count of the mask, if any.
+    pub fn mask_sample_count(&self) -> Option<u32> {
+        self.mask_sample_count
+    }
+
+    /// Sets the sample count of the mask.
+    ///
+    /// If `None`, the mask follows the `Msaa` resource. The count is lowered
+    /// to 8, 4, 2 or 1, whichever is the highest supported by the adapter.
+    /// A count of 1 disables multisampling of the mask, which reduces memory
+    /// use and bandwidth at the cost of aliased outline edges.
+    pub fn set_mask_sample_count(&mut self, value: Option<u32>) {
+        self.mask_sample_count = value;
+    }
+
+    /// Returns whether masks are drawn into a stencil target.
+    pub fn stencil_mask(&self) -> bool {
+        self.stencil_mask
+    }
+
+    /// Sets whether masks are drawn into a stencil target.
+    ///
+    /// When enabled, outlined meshes mark their pixels in a single-sampled
+    /// `Stencil8` target instead of writing object IDs, so overlapping
+    /// meshes merge into one silhouette without edges between them. The JFA
+    /// initialization pass is stencil-tested against the mask, so it only
+    /// shades pixels covered by outlined meshes and skips the background
+    /// around them.
+    ///
+    /// Per pixel, the mask then takes 3 bytes, against 8 bytes for the
+    /// single-sampled object ID mask and 34 bytes for a 4x multisampled one.
+    /// The mask sample count setting is ignored. The object ID mask is kept
+    /// on adapters which can't sample stencil textures, such as WebGL2.
+    pub fn set_stencil_mask(&mut self, value: bool) {
+        self.stencil_mask = value;
+    }
+
+    /// Returns whether masks are built from the prepass of cameras which have
+    /// one.
+    pub fn prepass_mask(&self) -> bool {
+        self.prepass_mask
+    }
+
+    /// Sets whether masks are built from the prepass of cameras which have
+    /// one.
+    ///
+    /// When enabled, cameras with both a `DepthPrepass` and a `NormalPrepass`
+    /// skip the multisampled mask draw. Outlined meshes only write their
+    /// object IDs and intensity into single-sampled targets, without a depth
+    /// target of their own: fragments behind the depth prepass are
+    /// discarded, so outlines share the occlusion of the main pass. The JFA
+    /// initialization pass places seeds on the silhouette along the normals
+    /// of the normal prepass, in place of the multisampled coverage.
+    ///
+    /// Masks are then single-sampled for every camera, and cameras without
+    /// both prepasses draw a single-sampled mask of their own. Stencil masks
+    /// take precedence, and the setting is ignored on WebGL2.
+    pub fn set_prepass_mask(&mut self, value: bool) {
+        self.prepass_mask = value;
+    }
 }
 
 impl Default for OutlineSettings {
     fn default() -> Self {
-        println!("creating outline settings");
         Self {
             half_resolution: false,
+            jump_schedule: JumpSchedule::default(),
+            debug_mode: None,
+            mask_sample_count: None,
+            stencil_mask: false,
+            prepass_mask: false,
         }
     }
 }
 
-const MASK_SHADER_HANDLE: UntypedHandle =
-    UntypedHandle::Weak(UntypedAssetId::Uuid {
-        type_id: TypeId::of::<Shader>(),
-        uuid: Uuid::from_u128(10400755559809425757),
-    });
-const JFA_INIT_SHADER_HANDLE: UntypedHandle =
-    UntypedHandle::Weak(UntypedAssetId::Uuid {
-        type_id: TypeId::of::<Shader>(),
-        uuid: Uuid::from_u128(11038189062916158841),
-    });
-const JFA_SHADER_HANDLE: UntypedHandle =
-    UntypedHandle::Weak(UntypedAssetId::Uuid {
-        type_id: TypeId::of::<Shader>(),
-        uuid: Uuid::from_u128(5227804998548228051),
-    });
-const FULLSCREEN_SHADER_HANDLE: UntypedHandle =
-    UntypedHandle::Weak(UntypedAssetId::Uuid {
-        type_id: TypeId::of::<Shader>(),
-        uuid: Uuid::from_u128(12099561278220359682),
-    });
-const OUTLINE_SHADER_HANDLE: UntypedHandle =
-    UntypedHandle::Weak(UntypedAssetId::Uuid {
-        type_id: TypeId::of::<Shader>(),
-        uuid: Uuid::from_u128(11094028876979933159),
-    });
-const DIMENSIONS_SHADER_HANDLE: UntypedHandle =
-    UntypedHandle::Weak(UntypedAssetId::Uuid {
-        type_id: TypeId::of::<Shader>(),
-        uuid: Uuid::from_u128(11721531257850828867),
-    });
+const MASK_SHADER_HANDLE: Handle<Shader> = Handle::weak_from_u128(10400755559809425757);
+const JFA_INIT_SHADER_HANDLE: Handle<Shader> = Handle::weak_from_u128(11038189062916158841);
+const JFA_SHADER_HANDLE: Handle<Shader> = Handle::weak_from_u128(5227804998548228051);
+const FULLSCREEN_SHADER_HANDLE: Handle<Shader> = Handle::weak_from_u128(12099561278220359682);
+const OUTLINE_SHADER_HANDLE: Handle<Shader> = Handle::weak_from_u128(11094028876979933159);
+const DIMENSIONS_SHADER_HANDLE: Handle<Shader> = Handle::weak_from_u128(11721531257850828867);
+const COMPOSITE_SHADER_HANDLE: Handle<Shader> = Handle::weak_from_u128(6402861539237416203);
+const MESH_MASK_SHADER_HANDLE: Handle<Shader> = Handle::weak_from_u128(14855034412375901267);
 
 use crate::graph::outline as outline_graph;
 
 impl Plugin for OutlinePlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(RenderAssetPlugin::<OutlineStyle>::default())
-            .init_asset::<OutlineStyle>()
-            .init_resource::<OutlineSettings>();
+        app.init_asset::<OutlineStyle>()
+            .register_asset_reflect::<OutlineStyle>()
+            .register_type::<Outline>()
+            .register_type::<OutlineIntensity>()
+            .register_type::<OutlineGroup>()
+            .register_type::<Option<OutlineGroup>>()
+            .register_type::<OutlineColor>()
+            .register_type::<Color>()
+            .register_type::<Option<Color>>()
+            .register_type::<OutlineWidth>()
+            .register_type::<Option<f32>>()
+            .register_type::<OutlineBlend>()
+            .register_type::<Option<OutlineBlendMode>>()
+            .register_type::<OutlineHierarchy>()
+            .register_type::<InheritedOutline>()
+            .register_type::<CameraOutline>()
+            .register_type::<RenderLayers>()
+            .register_type::<Option<RenderLayers>>()
+            .register_type::<OutlineSettings>()
+            .register_type::<JumpSchedule>()
+            .register_type::<Vec<u32>>()
+            .register_type::<Option<u32>>()
+            .register_type::<OutlineDebugMode>()
+            .register_type::<Option<OutlineDebugMode>>()
+            .register_type::<OutlineBlendMode>()
+            .init_asset_loader::<OutlineStyleLoader>()
+            .init_resource::<OutlineSettings>()
+            .init_resource::<diagnostics::OutlineStats>()
+            .add_systems(
+                PostUpdate,
+                (
+                    fade::animate_outline_intensity,
+                    hierarchy::propagate_outline_hierarchy,
+                )
+                    .chain(),
+            );
 
         let mut shaders = app.world.get_resource_mut::<Assets<Shader>>().unwrap();
 
         let mask_shader = Shader::from_wgsl(include_str!("shaders/mask.wgsl"), "shaders/mask.wgsl");
-        let jfa_init_shader = Shader::from_wgsl(include_str!("shaders/jfa_init.wgsl"), "shaders/jfa_init.wgsl");
+        let jfa_init_shader = Shader::from_wgsl(
+            include_str!("shaders/jfa_init.wgsl"),
+            "shaders/jfa_init.wgsl",
+        );
         let jfa_shader = Shader::from_wgsl(include_str!("shaders/jfa.wgsl"), "shaders/jfa.wgsl");
-        let fullscreen_shader = Shader::from_wgsl(include_str!("shaders/fullscreen.wgsl"), "shaders/fullscreen.wgsl")
-            .with_import_path("outline::fullscreen");
-        let outline_shader = Shader::from_wgsl(include_str!("shaders/outline.wgsl"), "shaders/outline.wgsl");
-        let dimensions_shader = Shader::from_wgsl(include_str!("shaders/dimensions.wgsl"), "shaders/dimensions.wgsl")
-            .with_import_path("outline::dimensions");
+        let fullscreen_shader = Shader::from_wgsl(
+            include_str!("shaders/fullscreen.wgsl"),
+            "shaders/fullscreen.wgsl",
+        )
+        .with_import_path("outline::fullscreen");
+        let outline_shader =
+            Shader::from_wgsl(include_str!("shaders/outline.wgsl"), "shaders/outline.wgsl");
+        let dimensions_shader = Shader::from_wgsl(
+            include_str!("shaders/dimensions.wgsl"),
+            "shaders/dimensions.wgsl",
+        )
+        .with_import_path("outline::dimensions");
+        let mesh_mask_shader = Shader::from_wgsl(
+            include_str!("shaders/mesh_mask.wgsl"),
+            "shaders/mesh_mask.wgsl",
+        )
+        .with_import_path("outline::mesh_mask");
+        let composite_shader = Shader::from_wgsl(
+            include_str!("shaders/composite.wgsl"),
+            "shaders/composite.wgsl",
+        )
+        .with_import_path("outline::composite");
 
         shaders.insert(MASK_SHADER_HANDLE, mask_shader);
         shaders.insert(JFA_INIT_SHADER_HANDLE, jfa_init_shader);
@@ -150,37 +360,111 @@ impl Plugin for OutlinePlugin {
         shaders.insert(FULLSCREEN_SHADER_HANDLE, fullscreen_shader);
         shaders.insert(OUTLINE_SHADER_HANDLE, outline_shader);
         shaders.insert(DIMENSIONS_SHADER_HANDLE, dimensions_shader);
+        shaders.insert(COMPOSITE_SHADER_HANDLE, composite_shader);
+        shaders.insert(MESH_MASK_SHADER_HANDLE, mesh_mask_shader);
+
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            // Cameras with a custom outline material skip their style.
+            material::add_outline_material_systems::<OutlineStyle, Without<CustomOutlineMaterial>>(
+                render_app,
+            );
+        }
     }
 
     fn finish(&self, app: &mut App) {
+        let stats = app.world.resource::<diagnostics::OutlineStats>().clone();
+
         let render_app = match app.get_sub_app_mut(RenderApp) {
             Ok(r) => r,
             Err(_) => return,
         };
 
         render_app
+            .insert_resource(stats)
             .init_resource::<DrawFunctions<MeshMask>>()
             .add_render_command::<MeshMask, SetItemPipeline>()
             .add_render_command::<MeshMask, DrawMeshMask>()
             .init_resource::<resources::OutlineResources>()
             .init_resource::<mask::MeshMaskPipeline>()
+            .init_resource::<mask::MeshMaskBindGroup>()
+            .init_resource::<MeshMaskMaterials>()
             .init_resource::<SpecializedMeshPipelines<mask::MeshMaskPipeline>>()
-            .init_resource::<jfa_init::JfaInitPipeline>()
-            .init_resource::<jfa::JfaPipeline>()
             .init_resource::<outline::OutlinePipeline>()
-            .init_resource::<SpecializedRenderPipelines<outline::OutlinePipeline>>()
-            .add_systems(ExtractSchedule, (
-                extract_outline_settings,
-                extract_camera_outlines,
-                extract_mask_camera_phase,
-                extract_outline_targets))
-            .add_systems(Render, (
-                resources::recreate_outline_resources,
-                queue_mesh_masks,
-            ).in_set(RenderSet::QueueMeshes))
-            .add_systems(Render, (
-                batch_and_prepare_render_phase::<MeshMask, MeshMaskPipeline>
-            ).in_set(RenderSet::PrepareResources));
+            .add_systems(
+                ExtractSchedule,
+                (
+                    extract_outline_settings,
+                    extract_camera_outlines,
+                    extract_mask_camera_phase,
+                    extract_outline_targets,
+                    mask::clear_mesh_mask_materials,
+                ),
+            )
+            .add_systems(
+                Render,
+                (resources::recreate_outline_resources, queue_mesh_masks)
+                    .chain()
+                    .in_set(RenderSet::QueueMeshes),
+            )
+            .add_systems(
+                Render,
+                sort_phase_system::<MeshMask>.in_set(RenderSet::PhaseSort),
+            )
+            .add_systems(
+                Render,
+                (batch_and_prepare_render_phase::<MeshMask, MeshMaskPipeline>)
+                    .in_set(RenderSet::PrepareResources),
+            )
+            .add_systems(
+                Render,
+                (write_batched_instance_buffer::<MeshMaskPipeline>)
+                    .in_set(RenderSet::PrepareResourcesFlush),
+            )
+            .add_systems(
+                Render,
+                (resources::prepare_object_styles).in_set(RenderSet::PrepareResources),
+            )
+            .add_systems(
+                Render,
+                (
+                    mask::prepare_mesh_mask_bind_group,
+                    resources::prepare_view_dimensions,
+                    resources::prepare_prepass_masks,
+                )
+                    .in_set(RenderSet::PrepareBindGroups),
+            )
+            .add_systems(
+                Render,
+                (bounds::prepare_outline_bounds).in_set(RenderSet::PrepareBindGroups),
+            );
+
+        // Outlines are disabled if there's no format to render the JFA
+        // textures with.
+        if render_app
+            .world
+            .resource::<OutlineResources>()
+            .jfa_texture_format
+            .is_some()
+        {
+            render_app
+                .init_resource::<jfa_init::JfaInitPipeline>()
+                .init_resource::<jfa::JfaPipeline>();
+        }
+
+        material::init_outline_material_resources::<OutlineStyle>(render_app);
+
+        #[cfg(feature = "wgpu-profiler")]
+        {
+            diagnostics::init_outline_profiler(&render_app.world);
+            render_app.add_systems(
+                Render,
+                diagnostics::end_outline_profiler_frame.in_set(RenderSet::Cleanup),
+            );
+        }
+
+        let mask_uniforms =
+            GpuArrayBuffer::<MeshMaskUniform>::new(render_app.world.resource::<RenderDevice>());
+        render_app.insert_resource(mask_uniforms);
 
         let render_app = match app.get_sub_app_mut(RenderApp) {
             Ok(r) => r,
@@ -194,12 +478,17 @@ impl Plugin for OutlinePlugin {
 
         draw_3d_graph.add_sub_graph(outline_graph::NAME, outline_graph);
         draw_3d_graph.add_node(OutlineDriverNode::NAME, OutlineDriverNode);
-        draw_3d_graph.add_node_edge(core_3d::graph::node::MAIN_TRANSPARENT_PASS, OutlineDriverNode::NAME);
+        draw_3d_graph.add_node_edge(
+            core_3d::graph::node::MAIN_TRANSPARENT_PASS,
+            OutlineDriverNode::NAME,
+        );
     }
 }
 
 struct MeshMask {
     distance: f32,
+    mesh: AssetId<Mesh>,
+    material: Option<UntypedAssetId>,
     pipeline: CachedRenderPipelineId,
     entity: Entity,
     draw_function: DrawFunctionId,
@@ -208,10 +497,17 @@ struct MeshMask {
 }
 
 impl PhaseItem for MeshMask {
-    type SortKey = FloatOrd;
+    // Items are grouped by mesh and mask material so they can be drawn
+    // instanced, then sorted front to back within each group. Overlaps are
+    // resolved by the mask's depth test.
+    type SortKey = (AssetId<Mesh>, MaskMaterialSortKey, Reverse<FloatOrd>);
 
     fn sort_key(&self) -> Self::SortKey {
-        FloatOrd(self.distance)
+        (
+            self.mesh,
+            MaskMaterialSortKey(self.material),
+            Reverse(FloatOrd(self.distance)),
+        )
     }
 
     fn draw_function(&self) -> DrawFunctionId {
@@ -239,6 +535,32 @@ impl PhaseItem for MeshMask {
     }
 }
 
+/// Orders the mask materials of [`MeshMask`] items, which `UntypedAssetId`
+/// doesn't do itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct MaskMaterialSortKey(Option<UntypedAssetId>);
+
+impl MaskMaterialSortKey {
+    fn ord_key(&self) -> Option<(TypeId, Result<AssetIndex, Uuid>)> {
+        self.0.map(|material| match material {
+            UntypedAssetId::Index { type_id, index } => (type_id, Ok(index)),
+            UntypedAssetId::Uuid { type_id, uuid } => (type_id, Err(uuid)),
+        })
+    }
+}
+
+impl PartialOrd for MaskMaterialSortKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for MaskMaterialSortKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.ord_key().cmp(&other.ord_key())
+    }
+}
+
 impl CachedRenderPipelinePhaseItem for MeshMask {
     fn cached_pipeline(&self) -> CachedRenderPipelineId {
         self.pipeline
@@ -248,66 +570,145 @@ impl CachedRenderPipelinePhaseItem for MeshMask {
 type DrawMeshMask = (
     SetItemPipeline,
     SetMeshViewBindGroup<0>,
-    SetMeshBindGroup<1>,
+    SetMeshMaskBindGroup<1>,
     DrawMesh,
 );
 
 /// Visual style for an outline.
-#[derive(Asset, TypePath, Clone, Debug, PartialEq)]
+///
+/// Styles can be loaded from `*.outline.ron` files. Omitted fields keep
+/// their default values:
+///
+/// ```ron
+/// (
+///     color: Rgba(red: 1.0, green: 0.5, blue: 0.0, alpha: 1.0),
+///     width: 16.0,
+///     blend_mode: Additive,
+/// )
+/// ```
+#[derive(Asset, AsBindGroup, Reflect, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[reflect(Serialize, Deserialize)]
+#[serde(default)]
+#[uniform(0, OutlineParams)]
 pub struct OutlineStyle {
     pub color: Color,
     pub inner_color: Color,
     pub width: f32,
+    pub blend_mode: OutlineBlendMode,
 }
 
-impl RenderAsset for OutlineStyle {
-    type ExtractedAsset = OutlineParams;
-    type PreparedAsset = GpuOutlineParams;
-    type Param = (
-        Res<'static, RenderDevice>,
-        Res<'static, RenderQueue>,
-        Res<'static, OutlineResources>,
-    );
+impl Default for OutlineStyle {
+    fn default() -> Self {
+        OutlineStyle {
+            color: Color::WHITE,
+            inner_color: Color::NONE,
+            width: 8.0,
+            blend_mode: OutlineBlendMode::default(),
+        }
+    }
+}
 
-    fn extract_asset(&self) -> Self::ExtractedAsset {
+impl AsBindGroupShaderType<OutlineParams> for OutlineStyle {
+    fn as_bind_group_shader_type(&self, _images: &RenderAssets<Image>) -> OutlineParams {
         OutlineParams::new(self.color, self.inner_color, self.width)
     }
+}
+
+impl OutlineMaterial for OutlineStyle {
+    fn width(&self) -> f32 {
+        self.width
+    }
 
-    fn prepare_asset(
-        extracted_asset: Self::ExtractedAsset,
-        (device, queue, outline_res): &mut SystemParamItem<Self::Param>,
-    ) -> Result<Self::PreparedAsset, PrepareAssetError<Self::ExtractedAsset>> {
-        let mut buffer = UniformBuffer::from(extracted_asset.clone());
-        buffer.write_buffer(device, queue);
-
-        let bind_group = device.create_bind_group(None,
-            &outline_res.outline_params_bind_group_layout,
-            &[BindGroupEntry {
-                binding: 0,
-                resource: buffer.buffer().unwrap().as_entire_binding(),
-            }]);
-
-        Ok(GpuOutlineParams {
-            params: extracted_asset,
-            _buffer: buffer,
-            bind_group,
-        })
+    fn blend_mode(&self) -> OutlineBlendMode {
+        self.blend_mode
     }
 }
 
 /// Component for enabling outlines when rendering with a given camera.
-#[derive(Clone, Debug, PartialEq, Component)]
+#[derive(Clone, Debug, PartialEq, Component, Reflect)]
+#[reflect(Component, Default)]
 pub struct CameraOutline {
     pub enabled: bool,
+    /// The style of the outlines.
+    ///
+    /// Bevy can only serialize weak handles with a UUID, so cameras saved in
+    /// a `DynamicScene` should use a handle from [`Handle::weak_from_u128`],
+    /// with the style inserted into `Assets<OutlineStyle>` under that handle.
     pub style: Handle<OutlineStyle>,
+    /// Render layers whose entities should be outlined by this camera.
+    ///
+    /// If `None`, the camera's own `RenderLayers` are used. An entity is only
+    /// outlined if its `RenderLayers` intersect both these layers and the
+    /// camera's `RenderLayers`.
+    pub layers: Option<RenderLayers>,
+}
+
+impl Default for CameraOutline {
+    fn default() -> Self {
+        CameraOutline {
+            enabled: true,
+            style: Handle::default(),
+            layers: None,
+        }
+    }
 }
 
 /// Component for entities that should be outlined.
-#[derive(Clone, Debug, PartialEq, Component)]
+///
+/// Add an [`OutlineIntensity`] to fade the outline in and out when `enabled`
+/// changes.
+#[derive(Clone, Debug, PartialEq, Component, Reflect)]
+#[reflect(Component, Default)]
 pub struct Outline {
     pub enabled: bool,
 }
 
+impl Default for Outline {
+    fn default() -> Self {
+        Outline { enabled: true }
+    }
+}
+
+/// Component for merging the outlines of several entities.
+///
+/// Outlined entities in the same group are treated as a single silhouette, so
+/// no outline is drawn where they touch or overlap. Entities in different
+/// groups, or without a group, keep their own outlines where they overlap.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Component, Reflect)]
+#[reflect(Component, Default)]
+pub struct OutlineGroup(pub u32);
+
+/// Component for overriding the outline color of an entity.
+///
+/// The outline keeps the width and blend mode of the camera's
+/// [`OutlineStyle`] unless they are overridden with an [`OutlineWidth`] and
+/// [`OutlineBlend`]. Entities merged into a single silhouette by an
+/// [`OutlineGroup`] or [`OutlineHierarchy`] share the overrides of one of
+/// them. Overrides are ignored with stencil masks, which don't tell meshes
+/// apart; see [`OutlineSettings::set_stencil_mask`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Component, Reflect)]
+#[reflect(Component, Default)]
+pub struct OutlineColor(pub Color);
+
+/// Component for overriding the outline width of an entity, in pixels.
+///
+/// The jump flood of every camera reaches as far as the widest outline, so
+/// wide overrides cost as much as a wide camera style. See [`OutlineColor`]
+/// for how overrides are shared and when they are ignored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Component, Reflect)]
+#[reflect(Component, Default)]
+pub struct OutlineWidth(pub f32);
+
+/// Component for overriding how the outline of an entity is blended with the
+/// view target.
+///
+/// Each blend mode in use other than that of the camera's style adds a draw
+/// to the composite pass. See [`OutlineColor`] for how overrides are shared
+/// and when they are ignored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Component, Reflect)]
+#[reflect(Component, Default)]
+pub struct OutlineBlend(pub OutlineBlendMode);
+
 fn extract_outline_settings(mut commands: Commands, settings: Extract<Res<OutlineSettings>>) {
     commands.insert_resource(settings.clone());
 }
@@ -321,7 +722,11 @@ fn extract_camera_outlines(
     batches.extend(
         cam_outline_query
             .iter()
-            .filter_map(|(entity, outline)| outline.enabled.then(|| (entity, (outline.clone(),)))),
+            .filter(|(_, outline)| outline.enabled)
+            .map(|(entity, outline)| {
+                let material = ViewOutlineMaterial::<OutlineStyle>(outline.style.id());
+                (entity, (outline.clone(), material))
+            }),
     );
     *previous_outline_len = batches.len();
     commands.insert_or_spawn_batch(batches);
@@ -338,65 +743,202 @@ fn extract_mask_camera_phase(
     }
 }
 
+/// The outline color, width and blend mode overrides of an entity.
+pub(crate) type OutlineOverrides = (
+    Option<&'static OutlineColor>,
+    Option<&'static OutlineWidth>,
+    Option<&'static OutlineBlend>,
+);
+
+/// Key for entities whose outlines are merged into a single silhouette.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum MergeKey {
+    Group(OutlineGroup),
+    Hierarchy(Entity),
+}
+
+type OutlineTargetQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        Option<&'static Outline>,
+        Option<&'static OutlineIntensity>,
+        OutlineOverrides,
+        Option<&'static InheritedOutline>,
+        &'static Handle<Mesh>,
+        &'static GlobalTransform,
+        Option<&'static RenderLayers>,
+        Option<&'static OutlineGroup>,
+        Option<&'static Aabb>,
+    ),
+    Or<(With<Outline>, With<InheritedOutline>)>,
+>;
+
 fn extract_outline_targets(
     mut commands: Commands,
-    query: Extract<Query<(Entity, &Outline, &Handle<Mesh>, &GlobalTransform)>>,
+    query: Extract<OutlineTargetQuery>,
+    mut merged_ids: Local<HashMap<MergeKey, u32>>,
 ) {
-    for (entity, outline, mesh, global_transform) in query.iter() {
-        if outline.enabled {
+    // Object IDs only need to be unique within a frame. Zero is reserved for
+    // the background, and IDs wrap to fit the 16-bit ID target. Entities in
+    // the same group or outline hierarchy share an ID so that the mask pass
+    // merges them.
+    let mut next_id = 0_u32;
+    let mut alloc_id = || {
+        let id = next_id % u16::MAX as u32 + 1;
+        next_id = next_id.wrapping_add(1);
+        id
+    };
+    merged_ids.clear();
+
+    for (
+        entity,
+        outline,
+        intensity,
+        (color, width, blend),
+        inherited,
+        mesh,
+        global_transform,
+        layers,
+        group,
+        aabb,
+    ) in query.iter()
+    {
+        let (intensity, (color, width, blend_mode), merge_key) = match (outline, inherited) {
+            // The mesh of an entity with its own outline merges with the
+            // descendants inheriting it.
+            (Some(outline), _) => (
+                OutlineIntensity::of(outline, intensity),
+                (
+                    color.map(|color| color.0),
+                    width.map(|width| width.0),
+                    blend.map(|blend| blend.0),
+                ),
+                group
+                    .copied()
+                    .map_or(MergeKey::Hierarchy(entity), MergeKey::Group),
+            ),
+            (None, Some(inherited)) => (
+                inherited.intensity,
+                (inherited.color, inherited.width, inherited.blend_mode),
+                group
+                    .or(inherited.group.as_ref())
+                    .copied()
+                    .map_or(MergeKey::Hierarchy(inherited.root), MergeKey::Group),
+            ),
+            (None, None) => continue,
+        };
+
+        // Disabled outlines stay in the mask until they have faded out.
+        if intensity > 0.0 {
+            let id = *merged_ids.entry(merge_key).or_insert_with(&mut alloc_id);
+
             let cmds = &mut commands.get_or_spawn(entity);
-                cmds.insert(ExtractedOutline {
-                    mesh: mesh.clone(),
-                    transform: global_transform.compute_matrix(),
-                });
+            cmds.insert(ExtractedOutline {
+                mesh: mesh.clone(),
+                transform: global_transform.compute_matrix(),
+                layers: layers.copied().unwrap_or_default(),
+                aabb: aabb.cloned(),
+                id,
+                intensity,
+                color,
+                width,
+                blend_mode,
+            });
         }
     }
 }
 
-fn queue_mesh_masks(
+type MeshMaskViewQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static ExtractedView,
+        &'static mut VisibleEntities,
+        &'static mut RenderPhase<MeshMask>,
+        Option<&'static CameraOutline>,
+        Option<&'static RenderLayers>,
+        ViewPrepasses,
+    ),
+>;
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn queue_mesh_masks(
     mesh_mask_draw_functions: Res<DrawFunctions<MeshMask>>,
     mesh_mask_pipeline: Res<MeshMaskPipeline>,
     mut pipelines: ResMut<SpecializedMeshPipelines<MeshMaskPipeline>>,
-    mut pipeline_cache: ResMut<PipelineCache>,
+    pipeline_cache: Res<PipelineCache>,
     render_meshes: Res<RenderAssets<Mesh>>,
+    outline_res: Res<OutlineResources>,
+    msaa: Res<Msaa>,
+    mask_materials: Res<MeshMaskMaterials>,
     outline_meshes: Query<(Entity, &ExtractedOutline)>,
-    mut views: Query<(
-        &ExtractedView,
-        &mut VisibleEntities,
-        &mut RenderPhase<MeshMask>,
-    )>,
+    mut views: MeshMaskViewQuery,
 ) {
     let draw_outline = mesh_mask_draw_functions
         .read()
         .get_id::<DrawMeshMask>()
         .unwrap();
 
-    for (view, visible_entities, mut mesh_mask_phase) in views.iter_mut() {
+    for (view, visible_entities, mut mesh_mask_phase, camera_outline, view_layers, prepasses) in
+        views.iter_mut()
+    {
+        let view_key = mask::view_mesh_key(&msaa, prepasses);
+
         let view_matrix = view.transform.compute_matrix();
         let inv_view_row_2 = view_matrix.inverse().row(2);
 
+        let view_layers = view_layers.copied().unwrap_or_default();
+        let outline_layers = camera_outline
+            .and_then(|outline| outline.layers)
+            .unwrap_or(view_layers);
+
         for visible_entity in visible_entities.entities.iter().copied() {
             let (entity, extracted_outline) = match outline_meshes.get(visible_entity) {
                 Ok(m) => m,
                 Err(_) => continue,
             };
 
+            if !view_layers.intersects(&extracted_outline.layers)
+                || !outline_layers.intersects(&extracted_outline.layers)
+            {
+                continue;
+            }
+
             let mesh = match render_meshes.get(&extracted_outline.mesh) {
                 Some(m) => m,
                 None => continue,
             };
 
-            let key = MeshPipelineKey::from_primitive_topology(mesh.primitive_topology);
-
-            let pipeline = pipelines
-                .specialize(&mut pipeline_cache, &mesh_mask_pipeline, key, &mesh.layout)
-                .unwrap();
+            // Meshes with a mask material were specialized by its plugin.
+            let (pipeline, draw_function, material) = match mask_materials.get(&entity) {
+                Some(material) => {
+                    let Some(&pipeline) = material.pipelines.get(&view_key) else {
+                        continue;
+                    };
+                    (pipeline, material.draw_function, Some(material.material))
+                }
+                None => {
+                    let key = MeshMaskPipelineKey {
+                        mesh_key: view_key
+                            | MeshPipelineKey::from_primitive_topology(mesh.primitive_topology),
+                        ..MeshMaskPipelineKey::for_view(&outline_res, view_key)
+                    };
+                    let pipeline = pipelines
+                        .specialize(&pipeline_cache, &mesh_mask_pipeline, key, &mesh.layout)
+                        .unwrap();
+                    (pipeline, draw_outline, None)
+                }
+            };
 
             mesh_mask_phase.add(MeshMask {
                 entity,
+                mesh: extracted_outline.mesh.id(),
+                material,
                 pipeline,
-                draw_function: draw_outline,
-                distance: inv_view_row_2.dot(extracted_outline.transform.col(2)),
+                draw_function,
+                distance: inv_view_row_2.dot(extracted_outline.transform.col(3)),
                 batch_range: 0..1,
                 dynamic_offset: None,
             });
@@ -405,25 +947,182 @@ fn queue_mesh_masks(
 }
 
 impl GetBatchData for MeshMaskPipeline {
-    type Param = SRes<RenderMeshInstances>;
-    type Query = Entity;
+    type Param = (SRes<RenderMeshInstances>, SRes<MeshMaskMaterials>);
+    type Query = (Entity, Read<ExtractedOutline>);
     type QueryFilter = With<Mesh3d>;
-    type CompareData = (MaterialBindGroupId, AssetId<Mesh>);
-    type BufferData = MeshUniform;
+    // Instances need the same mesh, and the same mask material if any.
+    type CompareData = (AssetId<Mesh>, Option<UntypedAssetId>);
+    type BufferData = MeshMaskUniform;
 
     fn get_batch_data(
-        mesh_instances: &SystemParamItem<Self::Param>,
-        entity: &QueryItem<Self::Query>,
+        (mesh_instances, mask_materials): &SystemParamItem<Self::Param>,
+        (entity, outline): &QueryItem<Self::Query>,
     ) -> (Self::BufferData, Option<Self::CompareData>) {
         let mesh_instance = mesh_instances
             .get(entity)
             .expect("Failed to find render mesh instance");
         (
-            (&mesh_instance.transforms).into(),
-            mesh_instance.automatic_batching.then_some((
-                mesh_instance.material_bind_group_id,
-                mesh_instance.mesh_asset_id,
-            )),
+            MeshMaskUniform::new(&outline.transform, outline.id, outline.intensity),
+            mesh_instance.automatic_batching.then(|| {
+                let material = mask_materials.get(entity).map(|material| material.material);
+                (mesh_instance.mesh_asset_id, material)
+            }),
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mask_batches_split_on_mesh_and_material() {
+        use bevy::{
+            ecs::system::{RunSystemOnce, StaticSystemParam},
+            math::Affine3A,
+            pbr::{MaterialBindGroupId, MeshTransforms, RenderMeshInstance, StandardMaterial},
+        };
+
+        use crate::mask::MeshMaskMaterial;
+
+        let mut app = App::new();
+        app.init_resource::<DrawFunctions<MeshMask>>()
+            .init_resource::<RenderMeshInstances>()
+            .init_resource::<MeshMaskMaterials>()
+            .insert_resource(GpuArrayBuffer::<MeshMaskUniform>::Storage((
+                StorageBuffer::default(),
+                Vec::new(),
+            )))
+            .add_render_command::<MeshMask, SetItemPipeline>();
+        let draw_function = app
+            .world
+            .resource::<DrawFunctions<MeshMask>>()
+            .read()
+            .id::<SetItemPipeline>();
+
+        let meshes = [Handle::weak_from_u128(1), Handle::weak_from_u128(2)];
+        let materials = [
+            Handle::<StandardMaterial>::weak_from_u128(3).id().untyped(),
+            Handle::<StandardMaterial>::weak_from_u128(4).id().untyped(),
+        ];
+        // Mesh, mask material, distance and automatic batching of each item,
+        // in the order they are drawn after sorting. The mask materials of
+        // the first mesh alternate by distance, so sorting by mesh and
+        // distance alone would interleave them.
+        let specs = [
+            (0, None, 6.0, true),
+            (0, None, 3.0, true),
+            (0, Some(0), 5.0, true),
+            (0, Some(0), 2.0, true),
+            (0, Some(1), 4.0, true),
+            (1, None, 2.0, true),
+            (1, None, 1.0, false),
+        ];
+
+        let mut items = Vec::new();
+        for (index, &(mesh, material, distance, automatic_batching)) in specs.iter().enumerate() {
+            let mesh: &Handle<Mesh> = &meshes[mesh];
+            let entity = app
+                .world
+                .spawn((
+                    ExtractedOutline {
+                        mesh: mesh.clone(),
+                        transform: Mat4::IDENTITY,
+                        layers: RenderLayers::default(),
+                        aabb: None,
+                        id: index as u32 + 1,
+                        intensity: 1.0,
+                        color: None,
+                        width: None,
+                        blend_mode: None,
+                    },
+                    Mesh3d,
+                ))
+                .id();
+            app.world.resource_mut::<RenderMeshInstances>().insert(
+                entity,
+                RenderMeshInstance {
+                    transforms: MeshTransforms {
+                        transform: (&Affine3A::IDENTITY).into(),
+                        previous_transform: (&Affine3A::IDENTITY).into(),
+                        flags: 0,
+                    },
+                    mesh_asset_id: mesh.id(),
+                    material_bind_group_id: MaterialBindGroupId::default(),
+                    shadow_caster: false,
+                    automatic_batching,
+                },
+            );
+            if let Some(material) = material {
+                app.world.resource_mut::<MeshMaskMaterials>().insert(
+                    entity,
+                    MeshMaskMaterial {
+                        pipelines: HashMap::default(),
+                        draw_function,
+                        material: materials[material],
+                    },
+                );
+            }
+            items.push(MeshMask {
+                distance,
+                mesh: mesh.id(),
+                material: material.map(|material| materials[material]),
+                pipeline: CachedRenderPipelineId::INVALID,
+                entity,
+                draw_function,
+                batch_range: 0..1,
+                dynamic_offset: None,
+            });
+        }
+        let entities = items.iter().map(|item| item.entity).collect::<Vec<_>>();
+
+        // Items are queued interleaved, as views find them.
+        let mut phase = RenderPhase::<MeshMask>::default();
+        for item in items.into_iter().rev() {
+            phase.add(item);
+        }
+        let view = app.world.spawn(phase).id();
+
+        app.world.run_system_once(sort_phase_system::<MeshMask>);
+        app.world
+            .run_system_once(batch_and_prepare_render_phase::<MeshMask, MeshMaskPipeline>);
+
+        let phase = app.world.get::<RenderPhase<MeshMask>>(view).unwrap();
+        let sorted = phase
+            .items
+            .iter()
+            .map(|item| item.entity)
+            .collect::<Vec<_>>();
+        assert_eq!(sorted, entities);
+        let ranges = phase
+            .items
+            .iter()
+            .map(|item| item.batch_range().clone())
+            .collect::<Vec<_>>();
+        // Each batch is drawn by its first item.
+        assert_eq!(ranges, [0..2, 1..2, 2..4, 3..4, 4..5, 5..6, 6..7]);
+        assert_eq!(mask::mask_draw_count(&phase.items), 5);
+
+        let compare_data = app.world.run_system_once(
+            move |param: StaticSystemParam<<MeshMaskPipeline as GetBatchData>::Param>,
+                  query: Query<<MeshMaskPipeline as GetBatchData>::Query>| {
+                entities
+                    .iter()
+                    .map(|&entity| {
+                        let item = query.get(entity).unwrap();
+                        MeshMaskPipeline::get_batch_data(&param, &item).1
+                    })
+                    .collect::<Vec<_>>()
+            },
+        );
+        let expected = specs.map(|(mesh, material, _, automatic_batching)| {
+            automatic_batching.then(|| {
+                (
+                    meshes[mesh].id(),
+                    material.map(|material: usize| materials[material]),
+                )
+            })
+        });
+        assert_eq!(compare_data, expected);
+    }
+}
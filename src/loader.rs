@@ -0,0 +1,46 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    utils::BoxedFuture,
+};
+use thiserror::Error;
+
+use crate::OutlineStyle;
+
+/// Loads [`OutlineStyle`]s from `*.outline.ron` files.
+///
+/// With Bevy's `file_watcher` feature and asset watching enabled, edits to a
+/// loaded file are applied to outlines while the app is running.
+#[derive(Default)]
+pub struct OutlineStyleLoader;
+
+/// An error produced while loading an [`OutlineStyle`].
+#[derive(Debug, Error)]
+pub enum OutlineStyleLoaderError {
+    #[error("could not read outline style: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse outline style: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+}
+
+impl AssetLoader for OutlineStyleLoader {
+    type Asset = OutlineStyle;
+    type Settings = ();
+    type Error = OutlineStyleLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<OutlineStyle, OutlineStyleLoaderError>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            Ok(ron::de::from_bytes(&bytes)?)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["outline.ron"]
+    }
+}
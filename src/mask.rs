@@ -1,69 +1,332 @@
 use bevy::{
-    ecs::system::lifetimeless::Read, pbr::{MeshPipeline, MeshPipelineKey, MeshPipelineViewLayoutKey}, prelude::*, render::{
-        batching::GetBatchData, mesh::MeshVertexBufferLayout, render_graph::{Node, RenderGraphContext, SlotInfo, SlotType}, render_phase::RenderPhase, render_resource::{
-            BlendComponent, BlendFactor, BlendOperation, BlendState, ColorTargetState, ColorWrites, FragmentState, LoadOp, MultisampleState, Operations, RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipelineDescriptor, SpecializedMeshPipeline, SpecializedMeshPipelineError, TextureFormat
-        }, renderer::RenderContext, view::ViewDepthTexture
-    }
+    asset::UntypedAssetId,
+    core_pipeline::prepass::{DeferredPrepass, DepthPrepass, MotionVectorPrepass, NormalPrepass},
+    ecs::{
+        query::Has,
+        system::{lifetimeless::SRes, SystemParamItem},
+    },
+    pbr::{MeshPipeline, MeshPipelineKey, MeshPipelineViewLayoutKey},
+    prelude::*,
+    render::{
+        camera::ExtractedCamera,
+        mesh::MeshVertexBufferLayout,
+        render_graph::{Node, RenderGraphContext, SlotInfo, SlotType},
+        render_phase::{
+            DrawFunctionId, PhaseItem, RenderCommand, RenderCommandResult, RenderPhase,
+            TrackedRenderPass,
+        },
+        render_resource::{
+            BindGroup, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BlendComponent,
+            BlendFactor, BlendOperation, BlendState, CachedRenderPipelineId, ColorTargetState,
+            ColorWrites, CompareFunction, DepthStencilState, FragmentState, GpuArrayBuffer, LoadOp,
+            MultisampleState, Operations, RenderPassColorAttachment,
+            RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipelineDescriptor,
+            ShaderDefVal, ShaderStages, ShaderType, SpecializedMeshPipeline,
+            SpecializedMeshPipelineError, StencilFaceState, StencilOperation, StencilState,
+            TextureFormat,
+        },
+        renderer::{RenderContext, RenderDevice},
+    },
+    utils::{EntityHashMap, HashMap},
+};
+
+use crate::{
+    diagnostics::{OutlineStage, OutlineStats},
+    resources::OutlineResources,
+    MeshMask, ViewOutlineBounds, MASK_SHADER_HANDLE,
 };
 
-use crate::{resources::OutlineResources, MeshMask, MASK_SHADER_HANDLE};
+/// Format of the coverage target written by the mask pass.
+///
+/// The red channel holds the coverage, and the green channel holds the
+/// outline intensity of the covering mesh.
+pub const MASK_COVERAGE_TEXTURE_FORMAT: TextureFormat = TextureFormat::Rg8Unorm;
+
+/// Format of the multisampled object ID target written by the mask pass.
+pub const MASK_ID_TEXTURE_FORMAT: TextureFormat = TextureFormat::R16Uint;
 
-#[derive(Resource)]
+/// Format of the multisampled depth target used by the mask pass.
+///
+/// Mask items are grouped by mesh for instancing rather than sorted by
+/// distance, so the depth test keeps the nearest object ID where meshes
+/// overlap.
+pub const MASK_DEPTH_TEXTURE_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+
+/// Format of the stencil target written by the mask pass in stencil mode.
+///
+/// Covered pixels have a stencil value of [`MASK_STENCIL_REFERENCE`], and
+/// take the place of the object IDs.
+pub const MASK_STENCIL_TEXTURE_FORMAT: TextureFormat = TextureFormat::Stencil8;
+
+/// Stencil value written for covered pixels in stencil mode.
+pub const MASK_STENCIL_REFERENCE: u32 = 1;
+
+/// Per-instance data for the mask pass.
+#[derive(Clone, Debug, ShaderType)]
+pub struct MeshMaskUniform {
+    // Rows of the affine model matrix.
+    pub model: [Vec4; 3],
+    // Object ID written to the ID target. Zero is reserved for the background.
+    pub id: u32,
+    // Outline intensity written to the coverage target.
+    pub intensity: f32,
+}
+
+impl MeshMaskUniform {
+    pub fn new(transform: &Mat4, id: u32, intensity: f32) -> MeshMaskUniform {
+        MeshMaskUniform {
+            model: [transform.row(0), transform.row(1), transform.row(2)],
+            id,
+            intensity,
+        }
+    }
+}
+
+#[derive(Clone, Resource)]
 pub struct MeshMaskPipeline {
     mesh_pipeline: MeshPipeline,
+    pub(crate) mask_layout: BindGroupLayout,
+    batch_size: Option<u32>,
 }
 
 impl FromWorld for MeshMaskPipeline {
     fn from_world(world: &mut World) -> Self {
         let mesh_pipeline = world.get_resource::<MeshPipeline>().unwrap().clone();
+        let device = world.resource::<RenderDevice>();
+
+        let mask_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("outline_mesh_mask_bind_group_layout"),
+            entries: &[GpuArrayBuffer::<MeshMaskUniform>::binding_layout(
+                0,
+                ShaderStages::VERTEX,
+                device,
+            )],
+        });
+        let batch_size = GpuArrayBuffer::<MeshMaskUniform>::batch_size(device);
 
-        MeshMaskPipeline { mesh_pipeline }
+        MeshMaskPipeline {
+            mesh_pipeline,
+            mask_layout,
+            batch_size,
+        }
     }
 }
 
-impl SpecializedMeshPipeline for MeshMaskPipeline {
-    type Key = MeshPipelineKey;
+/// Prepasses of a view, which add their textures to the view's mesh view bind
+/// group.
+pub(crate) type ViewPrepasses = (
+    Has<DepthPrepass>,
+    Has<NormalPrepass>,
+    Has<MotionVectorPrepass>,
+    Has<DeferredPrepass>,
+);
 
-    fn specialize(
+/// Returns the mesh pipeline key bits given by a view.
+///
+/// The mask pass binds the view's mesh view bind group, whose layout depends
+/// on the view's sample count and prepasses, so these bits are set as in
+/// Bevy's `queue_material_meshes`.
+pub(crate) fn view_mesh_key(
+    msaa: &Msaa,
+    (depth, normal, motion_vector, deferred): (bool, bool, bool, bool),
+) -> MeshPipelineKey {
+    let mut key = MeshPipelineKey::from_msaa_samples(msaa.samples());
+    if depth {
+        key |= MeshPipelineKey::DEPTH_PREPASS;
+    }
+    if normal {
+        key |= MeshPipelineKey::NORMAL_PREPASS;
+    }
+    if motion_vector {
+        key |= MeshPipelineKey::MOTION_VECTOR_PREPASS;
+    }
+    if deferred {
+        key |= MeshPipelineKey::DEFERRED_PREPASS;
+    }
+    key
+}
+
+/// Returns whether a view with the given mesh pipeline key bits builds its
+/// mask from its prepass.
+///
+/// Prepass masks need both the depth prepass, which occludes the mask, and
+/// the normal prepass, which orients the seeds of the JFA init pass.
+pub(crate) fn uses_prepass_mask(prepass_mask: bool, view_key: MeshPipelineKey) -> bool {
+    prepass_mask
+        && view_key.contains(MeshPipelineKey::DEPTH_PREPASS | MeshPipelineKey::NORMAL_PREPASS)
+}
+
+/// Key for specializing the mask pipeline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MeshMaskPipelineKey {
+    /// Key of the mesh, with the bits given by the view.
+    pub mesh_key: MeshPipelineKey,
+    /// Sample count of the mask targets.
+    pub mask_sample_count: u32,
+    /// Whether the mask is drawn into a stencil target rather than object ID
+    /// and depth targets.
+    pub stencil: bool,
+    /// Whether the mask is occluded by the view's depth prepass rather than
+    /// drawn with a depth target of its own.
+    pub prepass: bool,
+}
+
+impl MeshMaskPipelineKey {
+    /// Returns the key of the mask pipelines of a view, given its mesh
+    /// pipeline key bits from [`view_mesh_key`].
+    pub(crate) fn for_view(res: &OutlineResources, view_key: MeshPipelineKey) -> Self {
+        MeshMaskPipelineKey {
+            mesh_key: view_key,
+            mask_sample_count: res.mask_sample_count,
+            stencil: res.stencil_mask,
+            prepass: uses_prepass_mask(res.prepass_mask, view_key),
+        }
+    }
+
+    /// Returns the format of the depth or stencil target of the mask pass, if
+    /// it has one.
+    pub fn depth_stencil_format(&self) -> Option<TextureFormat> {
+        match (self.stencil, self.prepass) {
+            (true, _) => Some(MASK_STENCIL_TEXTURE_FORMAT),
+            (false, true) => None,
+            (false, false) => Some(MASK_DEPTH_TEXTURE_FORMAT),
+        }
+    }
+}
+
+impl MeshMaskPipeline {
+    /// Returns the mask pipeline for a mesh, optionally drawn with the bind
+    /// group layout and vertex shader of a material.
+    ///
+    /// The material bind group is bound at index 1, as for Bevy materials,
+    /// and the per-instance mask data follows it.
+    pub(crate) fn descriptor(
         &self,
-        key: Self::Key,
+        key: MeshMaskPipelineKey,
         layout: &MeshVertexBufferLayout,
+        material: Option<(&BindGroupLayout, Handle<Shader>)>,
     ) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError> {
-        let mut desc = self.mesh_pipeline.specialize(key, layout)?;
+        let mut desc = self.mesh_pipeline.specialize(key.mesh_key, layout)?;
 
-        desc.layout = vec![
-            self.mesh_pipeline.get_view_layout(MeshPipelineViewLayoutKey::MULTISAMPLED).clone(),
-            self.mesh_pipeline.mesh_layouts.model_only.clone(),
-        ];
+        // The view bind group is the one prepared for the main pass, so its
+        // layout follows the view's sample count and prepasses rather than
+        // the mask's.
+        desc.layout = vec![self
+            .mesh_pipeline
+            .get_view_layout(MeshPipelineViewLayoutKey::from(key.mesh_key))
+            .clone()];
 
+        let mut shader_defs = Vec::new();
+        if let Some(batch_size) = self.batch_size {
+            shader_defs.push(ShaderDefVal::UInt(
+                "MESH_MASK_BATCH_SIZE".into(),
+                batch_size,
+            ));
+        }
+
+        match material {
+            Some((material_layout, vertex_shader)) => {
+                desc.layout.push(material_layout.clone());
+                shader_defs.push(ShaderDefVal::UInt("MESH_MASK_BIND_GROUP".into(), 2));
+                // Material shaders may depend on the attributes of the mesh.
+                desc.vertex.shader = vertex_shader;
+                desc.vertex.shader_defs.extend(shader_defs.iter().cloned());
+            }
+            None => {
+                shader_defs.push(ShaderDefVal::UInt("MESH_MASK_BIND_GROUP".into(), 1));
+                desc.vertex.shader = MASK_SHADER_HANDLE;
+                desc.vertex.shader_defs = shader_defs.clone();
+            }
+        }
+        desc.layout.push(self.mask_layout.clone());
         desc.primitive.cull_mode = None;
-        desc.vertex.shader = MASK_SHADER_HANDLE.typed::<Shader>();
+
+        if key.stencil {
+            shader_defs.push("STENCIL_MASK".into());
+            desc.fragment = Some(FragmentState {
+                shader: MASK_SHADER_HANDLE,
+                shader_defs,
+                entry_point: "fragment".into(),
+                // Without a depth test, overlapping meshes keep the highest
+                // intensity.
+                targets: vec![Some(ColorTargetState {
+                    format: MASK_COVERAGE_TEXTURE_FORMAT,
+                    blend: Some(BlendState {
+                        color: BlendComponent {
+                            src_factor: BlendFactor::One,
+                            dst_factor: BlendFactor::One,
+                            operation: BlendOperation::Max,
+                        },
+                        alpha: BlendComponent::REPLACE,
+                    }),
+                    write_mask: ColorWrites::ALL,
+                })],
+            });
+            let stencil_face = StencilFaceState {
+                compare: CompareFunction::Always,
+                fail_op: StencilOperation::Keep,
+                depth_fail_op: StencilOperation::Keep,
+                pass_op: StencilOperation::Replace,
+            };
+            desc.depth_stencil = Some(DepthStencilState {
+                format: MASK_STENCIL_TEXTURE_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: CompareFunction::Always,
+                stencil: StencilState {
+                    front: stencil_face,
+                    back: stencil_face,
+                    read_mask: 0xff,
+                    write_mask: 0xff,
+                },
+                bias: default(),
+            });
+            desc.multisample = MultisampleState::default();
+
+            desc.label = Some("mesh_stencil_pipeline".into());
+            return Ok(desc);
+        }
+
+        if key.prepass {
+            shader_defs.push("PREPASS_MASK".into());
+            // The depth prepass in the view bind group is multisampled like
+            // the view.
+            if key.mesh_key.msaa_samples() > 1 {
+                shader_defs.push("MULTISAMPLED".into());
+            }
+        }
 
         desc.fragment = Some(FragmentState {
-            shader: MASK_SHADER_HANDLE.typed::<Shader>(),
-            shader_defs: vec![],
+            shader: MASK_SHADER_HANDLE,
+            shader_defs,
             entry_point: "fragment".into(),
-            targets: vec![Some(ColorTargetState {
-                format: TextureFormat::Rgba8Unorm,
-                blend: Some(BlendState {
-                    color: BlendComponent {
-                        src_factor: BlendFactor::One,
-                        dst_factor: BlendFactor::One,
-                        operation: BlendOperation::Add,
-                    },
-                    alpha: BlendComponent {
-                        src_factor: BlendFactor::One,
-                        dst_factor: BlendFactor::One,
-                        operation: BlendOperation::Add,
-                    },
+            targets: vec![
+                // Coverage
+                Some(ColorTargetState {
+                    format: MASK_COVERAGE_TEXTURE_FORMAT,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
                 }),
-                write_mask: ColorWrites::ALL,
-            })],
+                // Object ID
+                Some(ColorTargetState {
+                    format: MASK_ID_TEXTURE_FORMAT,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                }),
+            ],
+        });
+        // Bevy uses reverse-Z, so nearer fragments have greater depth. With a
+        // prepass mask, fragments behind the depth prepass are discarded by
+        // the shader instead, which leaves the nearest visible mesh.
+        desc.depth_stencil = key.depth_stencil_format().map(|format| DepthStencilState {
+            format,
+            depth_write_enabled: true,
+            depth_compare: CompareFunction::GreaterEqual,
+            stencil: default(),
+            bias: default(),
         });
-        desc.depth_stencil = None;
 
         desc.multisample = MultisampleState {
-            count: 4,
+            count: key.mask_sample_count,
             mask: !0,
             alpha_to_coverage_enabled: false,
         };
@@ -73,18 +336,106 @@ impl SpecializedMeshPipeline for MeshMaskPipeline {
     }
 }
 
+impl SpecializedMeshPipeline for MeshMaskPipeline {
+    type Key = MeshMaskPipelineKey;
+
+    fn specialize(
+        &self,
+        key: Self::Key,
+        layout: &MeshVertexBufferLayout,
+    ) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError> {
+        self.descriptor(key, layout, None)
+    }
+}
+
+/// Bind group for the per-instance mask data of the current frame.
+#[derive(Default, Resource)]
+pub struct MeshMaskBindGroup {
+    bind_group: Option<BindGroup>,
+}
+
+pub fn prepare_mesh_mask_bind_group(
+    mut mask_bind_group: ResMut<MeshMaskBindGroup>,
+    device: Res<RenderDevice>,
+    pipeline: Res<MeshMaskPipeline>,
+    mask_uniforms: Res<GpuArrayBuffer<MeshMaskUniform>>,
+) {
+    mask_bind_group.bind_group = mask_uniforms.binding().map(|binding| {
+        device.create_bind_group(
+            "outline_mesh_mask_bind_group",
+            &pipeline.mask_layout,
+            &[BindGroupEntry {
+                binding: 0,
+                resource: binding,
+            }],
+        )
+    });
+}
+
+pub struct SetMeshMaskBindGroup<const I: usize>;
+
+impl<P: PhaseItem, const I: usize> RenderCommand<P> for SetMeshMaskBindGroup<I> {
+    type Param = SRes<MeshMaskBindGroup>;
+    type ViewWorldQuery = ();
+    type ItemWorldQuery = ();
+
+    fn render<'w>(
+        item: &P,
+        _view: (),
+        _item_query: (),
+        mask_bind_group: SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let Some(bind_group) = mask_bind_group.into_inner().bind_group.as_ref() else {
+            return RenderCommandResult::Failure;
+        };
+
+        match item.dynamic_offset() {
+            Some(offset) => pass.set_bind_group(I, bind_group, &[offset.get()]),
+            None => pass.set_bind_group(I, bind_group, &[]),
+        }
+
+        RenderCommandResult::Success
+    }
+}
+
+/// Mask pipelines and draw function of an outlined entity drawn with a mask
+/// material.
+pub struct MeshMaskMaterial {
+    // Pipelines for each kind of view with a mask phase.
+    pub(crate) pipelines: HashMap<MeshPipelineKey, CachedRenderPipelineId>,
+    pub(crate) draw_function: DrawFunctionId,
+    /// Instances are only batched with others using the same material.
+    pub(crate) material: UntypedAssetId,
+}
+
+/// Outlined entities drawn with a mask material this frame.
+///
+/// Other entities are drawn with the built-in mask shader.
+#[derive(Default, Resource, Deref, DerefMut)]
+pub struct MeshMaskMaterials(EntityHashMap<Entity, MeshMaskMaterial>);
+
+pub fn clear_mesh_mask_materials(mut materials: ResMut<MeshMaskMaterials>) {
+    materials.clear();
+}
+
 /// Render graph node for producing stencils from meshes.
 pub struct MeshMaskNode {
-    query: QueryState<&'static RenderPhase<MeshMask>>,
+    query: QueryState<(&'static RenderPhase<MeshMask>, ViewPrepasses)>,
 }
 
 impl MeshMaskNode {
-    /// The produced stencil buffer.
+    /// The produced object ID buffer.
+    ///
+    /// This is a texture with format [`MASK_ID_TEXTURE_FORMAT`], multisampled
+    /// unless the mask sample count is 1.
+    /// Fragments covered by a mesh are assigned that mesh's object ID. All
+    /// other fragments are assigned a value of 0.
     ///
-    /// This has format `TextureFormat::Depth24PlusStencil8`. Fragments covered
-    /// by a mesh are assigned a value of 255. All other fragments are assigned
-    /// a value of 0. The depth aspect is unused.
-    pub const OUT_MASK: &'static str = "stencil";
+    /// In stencil mode, this is instead a single-sampled texture with format
+    /// [`MASK_STENCIL_TEXTURE_FORMAT`], where fragments covered by any mesh
+    /// are assigned [`MASK_STENCIL_REFERENCE`].
+    pub const OUT_MASK: &'static str = "mask_id";
 
     pub fn new(world: &mut World) -> MeshMaskNode {
         MeshMaskNode {
@@ -93,6 +444,24 @@ impl MeshMaskNode {
     }
 }
 
+/// Returns the number of draw calls issued for the batched items of a mask
+/// phase.
+///
+/// Each batch is drawn by its first item, with the instances of the following
+/// items in the batch.
+pub(crate) fn mask_draw_count(items: &[MeshMask]) -> u32 {
+    let mut draws = 0;
+    let mut index = 0;
+    while index < items.len() {
+        let batch_len = items[index].batch_range().len();
+        if batch_len > 0 {
+            draws += 1;
+        }
+        index += batch_len.max(1);
+    }
+    draws
+}
+
 impl Node for MeshMaskNode {
     fn input(&self) -> Vec<SlotInfo> {
         vec![]
@@ -112,32 +481,144 @@ impl Node for MeshMaskNode {
         render_context: &mut RenderContext,
         world: &World,
     ) -> Result<(), bevy::render::render_graph::NodeRunError> {
+        let view_entity = graph.view_entity();
         let res = world.get_resource::<OutlineResources>().unwrap();
+        let target_res = res.view_target(world, view_entity).unwrap();
 
         graph
-            .set_output(Self::OUT_MASK, res.mask_multisample.default_view.clone())
+            .set_output(
+                Self::OUT_MASK,
+                target_res.mask_id_multisample.default_view.clone(),
+            )
             .unwrap();
 
-        let view_entity = graph.view_entity();
-        let Ok(stencil_phase) = self.query.get_manual(world, view_entity) else {
+        let Ok((stencil_phase, prepasses)) = self.query.get_manual(world, view_entity) else {
             return Ok(());
         };
+        let view_key = view_mesh_key(world.resource::<Msaa>(), prepasses);
+        let key = MeshMaskPipelineKey::for_view(res, view_key);
 
-        let mut tracked_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
-            label: Some("outline_stencil_render_pass"),
-            color_attachments: &[Some(RenderPassColorAttachment {
-                view: &res.mask_multisample.default_view,
-                resolve_target: Some(&res.mask_output.default_view),
+        let stats = world.resource::<OutlineStats>();
+        stats.begin_stage(OutlineStage::Mask, render_context);
+
+        // Without multisampling, coverage is written to the output directly.
+        let resolve_target =
+            (res.mask_sample_count > 1).then_some(&*target_res.mask_output.default_view);
+
+        let depth_stencil_attachment = match key.depth_stencil_format() {
+            // The stencil is read by the passes that follow.
+            Some(MASK_STENCIL_TEXTURE_FORMAT) => Some(RenderPassDepthStencilAttachment {
+                view: &target_res.mask_depth_multisample.default_view,
+                depth_ops: None,
+                stencil_ops: Some(Operations {
+                    load: LoadOp::Clear(0),
+                    store: true,
+                }),
+            }),
+            Some(_) => Some(RenderPassDepthStencilAttachment {
+                view: &target_res.mask_depth_multisample.default_view,
+                depth_ops: Some(Operations {
+                    load: LoadOp::Clear(0.0),
+                    store: false,
+                }),
+                stencil_ops: None,
+            }),
+            // Prepass masks are occluded by the depth prepass in the shader.
+            None => None,
+        };
+
+        let color_attachments = [
+            Some(RenderPassColorAttachment {
+                view: &target_res.mask_multisample.default_view,
+                resolve_target,
+                ops: Operations {
+                    load: LoadOp::Clear(Color::BLACK.into()),
+                    store: true,
+                },
+            }),
+            Some(RenderPassColorAttachment {
+                view: &target_res.mask_id_multisample.default_view,
+                resolve_target: None,
                 ops: Operations {
                     load: LoadOp::Clear(Color::BLACK.into()),
                     store: true,
                 },
-            })],
-            depth_stencil_attachment: None,
+            }),
+        ];
+        // Stencil masks write no object IDs.
+        let color_attachments = match res.stencil_mask {
+            true => &color_attachments[..1],
+            false => &color_attachments[..],
+        };
+
+        let mut tracked_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+            label: Some("outline_stencil_render_pass"),
+            color_attachments,
+            depth_stencil_attachment,
         });
+        if res.stencil_mask {
+            tracked_pass.set_stencil_reference(MASK_STENCIL_REFERENCE);
+        }
 
+        // Draw the meshes where the view renders them in its target.
+        if let Some(viewport) = world
+            .get::<ExtractedCamera>(view_entity)
+            .and_then(|camera| camera.viewport.as_ref())
+        {
+            tracked_pass.set_camera_viewport(viewport);
+        }
+        if let Some(bounds) = world.get::<ViewOutlineBounds>(view_entity) {
+            bounds.set_scissor(&mut tracked_pass);
+        }
         stencil_phase.render(&mut tracked_pass, world, view_entity);
+        drop(tracked_pass);
+
+        stats.pass_recorded(OutlineStage::Mask);
+        stats.mask_draws_recorded(mask_draw_count(&stencil_phase.items));
+        stats.end_stage(render_context);
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view_key(prepasses: (bool, bool)) -> MeshMaskPipelineKey {
+        let (depth, normal) = prepasses;
+        let mesh_key = view_mesh_key(&Msaa::Sample4, (depth, normal, false, false));
+        MeshMaskPipelineKey {
+            mesh_key,
+            mask_sample_count: 1,
+            stencil: false,
+            prepass: uses_prepass_mask(true, mesh_key),
+        }
+    }
+
+    #[test]
+    fn prepass_views_skip_the_mask_depth_target() {
+        let key = view_key((true, true));
+        assert!(key.prepass);
+        assert_eq!(key.depth_stencil_format(), None);
+
+        // Views missing either prepass draw the mask with its own depth.
+        for prepasses in [(true, false), (false, true), (false, false)] {
+            let key = view_key(prepasses);
+            assert!(!key.prepass);
+            assert_eq!(key.depth_stencil_format(), Some(MASK_DEPTH_TEXTURE_FORMAT));
+        }
+
+        let mesh_key = view_mesh_key(&Msaa::Sample4, (true, true, false, false));
+        assert!(!uses_prepass_mask(false, mesh_key));
+
+        let stencil = MeshMaskPipelineKey {
+            stencil: true,
+            ..key
+        };
+        assert_eq!(
+            stencil.depth_stencil_format(),
+            Some(MASK_STENCIL_TEXTURE_FORMAT)
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use std::{hash::Hash, marker::PhantomData};
+
+use bevy::{
+    pbr::{
+        DrawMesh, Material, MaterialPipeline, MaterialPipelineKey, MeshPipelineKey,
+        RenderMaterialInstances, RenderMaterials, SetMaterialBindGroup, SetMeshViewBindGroup,
+    },
+    prelude::*,
+    render::{
+        mesh::MeshVertexBufferLayout,
+        render_asset::RenderAssets,
+        render_phase::{AddRenderCommand, DrawFunctions, RenderPhase, SetItemPipeline},
+        render_resource::{
+            PipelineCache, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipeline,
+            SpecializedMeshPipelineError, SpecializedMeshPipelines,
+        },
+        Render, RenderApp, RenderSet,
+    },
+};
+
+use crate::{
+    mask::{
+        self, MeshMaskMaterial, MeshMaskMaterials, MeshMaskPipeline, MeshMaskPipelineKey,
+        SetMeshMaskBindGroup, ViewPrepasses,
+    },
+    resources::{self, OutlineResources},
+    ExtractedOutline, MeshMask,
+};
+
+/// A [`Material`] whose meshes are drawn into the outline mask with a custom
+/// vertex shader.
+///
+/// Materials which move vertices in their own vertex shader, such as water or
+/// wind-blown foliage, can implement this trait so their outlines match the
+/// drawn geometry. Add the material's [`OutlineMaskMaterialPlugin`] to the
+/// app; outlined meshes with a `Handle<M>` are then drawn into the mask with
+/// [`OutlineMaskMaterial::mask_vertex_shader`] and the material's bind group,
+/// and `M::specialize` is called as for the material's prepass.
+///
+/// # Shaders
+///
+/// The material is bound to bind group 1, as in the material's own shaders.
+/// Vertex shaders compute the deformed position and leave the rest to the
+/// `outline::mesh_mask` import:
+///
+/// ```wgsl
+/// #import outline::mesh_mask::{globals, mask_vertex_output, VertexOutput}
+///
+/// @group(1) @binding(0)
+/// var<uniform> amplitude: f32;
+///
+/// @vertex
+/// fn vertex(
+///     @builtin(instance_index) instance_index: u32,
+///     @location(0) position: vec3<f32>,
+/// ) -> VertexOutput {
+///     var deformed = position;
+///     deformed.y += amplitude * sin(globals.time + position.x);
+///     return mask_vertex_output(instance_index, deformed);
+/// }
+/// ```
+///
+/// The `outline::mesh_mask` import provides:
+///
+/// - `mask_vertex_output(instance_index, position)`, which transforms a
+///   position in the mesh's local space into the output expected by the mask
+///   fragment shader.
+/// - `get_model_matrix(instance_index)`, the model matrix of the instance.
+/// - `view` and `globals`, Bevy's view and globals uniforms.
+///
+/// Vertex attributes are at the locations used by Bevy's mesh pipeline, with
+/// shader defs such as `VERTEX_UVS` for the attributes of the mesh.
+///
+/// Outlines are scissored to the bounds of the outlined meshes. Deformed
+/// meshes may leave their `Aabb`, so views drawing any of them scissor
+/// outlines to their whole viewport instead.
+pub trait OutlineMaskMaterial: Material {
+    /// Returns the vertex shader of the mask pass.
+    fn mask_vertex_shader() -> ShaderRef;
+}
+
+/// Plugin for drawing the outline masks of meshes with an
+/// [`OutlineMaskMaterial`].
+///
+/// Must be added after the [`OutlinePlugin`](crate::OutlinePlugin) and the
+/// material's `MaterialPlugin`.
+pub struct OutlineMaskMaterialPlugin<M: OutlineMaskMaterial>(PhantomData<M>);
+
+impl<M: OutlineMaskMaterial> Default for OutlineMaskMaterialPlugin<M> {
+    fn default() -> Self {
+        OutlineMaskMaterialPlugin(PhantomData)
+    }
+}
+
+impl<M: OutlineMaskMaterial> Plugin for OutlineMaskMaterialPlugin<M>
+where
+    M::Data: PartialEq + Eq + Hash + Clone,
+{
+    fn build(&self, app: &mut App) {
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app
+                .init_resource::<SpecializedMeshPipelines<OutlineMaskMaterialPipeline<M>>>()
+                .add_systems(
+                    Render,
+                    queue_mesh_mask_materials::<M>
+                        .in_set(RenderSet::QueueMeshes)
+                        .after(resources::recreate_outline_resources)
+                        .before(crate::queue_mesh_masks),
+                );
+        }
+    }
+
+    fn finish(&self, app: &mut App) {
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app
+                .add_render_command::<MeshMask, DrawMeshMaskMaterial<M>>()
+                .init_resource::<OutlineMaskMaterialPipeline<M>>();
+        }
+    }
+}
+
+type DrawMeshMaskMaterial<M> = (
+    SetItemPipeline,
+    SetMeshViewBindGroup<0>,
+    SetMaterialBindGroup<M, 1>,
+    SetMeshMaskBindGroup<2>,
+    DrawMesh,
+);
+
+/// The mask pipeline of a mask material.
+#[derive(Resource)]
+pub struct OutlineMaskMaterialPipeline<M: OutlineMaskMaterial> {
+    mask: MeshMaskPipeline,
+    material: MaterialPipeline<M>,
+    vertex_shader: Handle<Shader>,
+}
+
+impl<M: OutlineMaskMaterial> FromWorld for OutlineMaskMaterialPipeline<M> {
+    fn from_world(world: &mut World) -> Self {
+        let mask = world.resource::<MeshMaskPipeline>().clone();
+        let material = world.resource::<MaterialPipeline<M>>().clone();
+        let vertex_shader = match M::mask_vertex_shader() {
+            ShaderRef::Default => crate::MASK_SHADER_HANDLE,
+            ShaderRef::Handle(handle) => handle,
+            ShaderRef::Path(path) => world.resource::<AssetServer>().load(path),
+        };
+
+        OutlineMaskMaterialPipeline {
+            mask,
+            material,
+            vertex_shader,
+        }
+    }
+}
+
+/// Key for specializing the mask pipeline of a mask material.
+pub struct OutlineMaskMaterialKey<M: OutlineMaskMaterial> {
+    pub mask_key: MeshMaskPipelineKey,
+    pub bind_group_data: M::Data,
+}
+
+impl<M: OutlineMaskMaterial> Eq for OutlineMaskMaterialKey<M> where M::Data: PartialEq {}
+
+impl<M: OutlineMaskMaterial> PartialEq for OutlineMaskMaterialKey<M>
+where
+    M::Data: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.mask_key == other.mask_key && self.bind_group_data == other.bind_group_data
+    }
+}
+
+impl<M: OutlineMaskMaterial> Clone for OutlineMaskMaterialKey<M>
+where
+    M::Data: Clone,
+{
+    fn clone(&self) -> Self {
+        OutlineMaskMaterialKey {
+            mask_key: self.mask_key,
+            bind_group_data: self.bind_group_data.clone(),
+        }
+    }
+}
+
+impl<M: OutlineMaskMaterial> Hash for OutlineMaskMaterialKey<M>
+where
+    M::Data: Hash,
+{
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.mask_key.hash(state);
+        self.bind_group_data.hash(state);
+    }
+}
+
+impl<M: OutlineMaskMaterial> SpecializedMeshPipeline for OutlineMaskMaterialPipeline<M>
+where
+    M::Data: PartialEq + Eq + Hash + Clone,
+{
+    type Key = OutlineMaskMaterialKey<M>;
+
+    fn specialize(
+        &self,
+        key: Self::Key,
+        layout: &MeshVertexBufferLayout,
+    ) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError> {
+        let mut desc = self.mask.descriptor(
+            key.mask_key,
+            layout,
+            Some((&self.material.material_layout, self.vertex_shader.clone())),
+        )?;
+
+        M::specialize(
+            &self.material,
+            &mut desc,
+            layout,
+            MaterialPipelineKey {
+                mesh_key: key.mask_key.mesh_key,
+                bind_group_data: key.bind_group_data,
+            },
+        )?;
+        Ok(desc)
+    }
+}
+
+/// Specializes the mask pipeline of each outlined entity with a mask material
+/// of type `M`.
+#[allow(clippy::too_many_arguments)]
+fn queue_mesh_mask_materials<M: OutlineMaskMaterial>(
+    draw_functions: Res<DrawFunctions<MeshMask>>,
+    material_pipeline: Res<OutlineMaskMaterialPipeline<M>>,
+    mut pipelines: ResMut<SpecializedMeshPipelines<OutlineMaskMaterialPipeline<M>>>,
+    pipeline_cache: Res<PipelineCache>,
+    render_meshes: Res<RenderAssets<Mesh>>,
+    render_materials: Res<RenderMaterials<M>>,
+    material_instances: Res<RenderMaterialInstances<M>>,
+    outline_res: Res<OutlineResources>,
+    msaa: Res<Msaa>,
+    mut mask_materials: ResMut<MeshMaskMaterials>,
+    outline_meshes: Query<(Entity, &ExtractedOutline)>,
+    views: Query<ViewPrepasses, With<RenderPhase<MeshMask>>>,
+) where
+    M::Data: PartialEq + Eq + Hash + Clone,
+{
+    let draw_function = draw_functions
+        .read()
+        .get_id::<DrawMeshMaskMaterial<M>>()
+        .unwrap();
+
+    // Pipelines are specialized for every kind of view with a mask phase.
+    let mut view_keys = Vec::new();
+    for prepasses in views.iter() {
+        let view_key = mask::view_mesh_key(&msaa, prepasses);
+        if !view_keys.contains(&view_key) {
+            view_keys.push(view_key);
+        }
+    }
+
+    for (entity, extracted_outline) in outline_meshes.iter() {
+        let Some(material_id) = material_instances.get(&entity) else {
+            continue;
+        };
+        // Until the material is prepared, the built-in mask is drawn instead.
+        let Some(material) = render_materials.get(material_id) else {
+            continue;
+        };
+        let Some(mesh) = render_meshes.get(&extracted_outline.mesh) else {
+            continue;
+        };
+
+        let specialized = view_keys
+            .iter()
+            .map(|&view_key| {
+                let key = OutlineMaskMaterialKey {
+                    mask_key: MeshMaskPipelineKey {
+                        mesh_key: view_key
+                            | MeshPipelineKey::from_primitive_topology(mesh.primitive_topology),
+                        ..MeshMaskPipelineKey::for_view(&outline_res, view_key)
+                    },
+                    bind_group_data: material.key.clone(),
+                };
+                let pipeline =
+                    pipelines.specialize(&pipeline_cache, &material_pipeline, key, &mesh.layout)?;
+                Ok((view_key, pipeline))
+            })
+            .collect::<Result<_, SpecializedMeshPipelineError>>();
+        let pipelines = match specialized {
+            Ok(pipelines) => pipelines,
+            Err(err) => {
+                error!("{}", err);
+                continue;
+            }
+        };
+
+        mask_materials.insert(
+            entity,
+            MeshMaskMaterial {
+                pipelines,
+                draw_function,
+                material: material_id.untyped(),
+            },
+        );
+    }
+}
@@ -0,0 +1,382 @@
+use std::marker::PhantomData;
+
+use bevy::{
+    asset::AssetEvent,
+    ecs::query::ReadOnlyWorldQuery,
+    prelude::*,
+    render::{
+        render_asset::{prepare_assets, RenderAssets},
+        render_resource::{
+            AsBindGroup, AsBindGroupError, BindGroup, BindGroupLayout, CachedRenderPipelineId,
+            OwnedBindingResource, PipelineCache, RenderPipelineDescriptor, ShaderRef,
+            SpecializedRenderPipeline, SpecializedRenderPipelines,
+        },
+        renderer::RenderDevice,
+        texture::FallbackImage,
+        view::ViewTarget,
+        Extract, ExtractSchedule, Render, RenderApp, RenderSet,
+    },
+    utils::{HashMap, HashSet},
+};
+
+use crate::{
+    outline::{OutlineBlendMode, OutlinePipeline, OutlinePipelineKey},
+    resources::{prepare_object_styles, OutlineResources},
+    CameraOutline, OutlineSettings,
+};
+
+/// A material for compositing outlines onto the view target.
+///
+/// Outline materials are modeled on Bevy's `Material`: the fields of the
+/// material are bound to bind group 2 of the composite pass with
+/// [`AsBindGroup`], and [`OutlineMaterial::fragment_shader`] replaces the
+/// built-in composite. [`OutlineStyle`](crate::OutlineStyle) is the built-in
+/// implementation.
+///
+/// To composite the outlines of a camera with a custom material, add the
+/// material's [`OutlineMaterialPlugin`] to the app and a `Handle<M>` next to
+/// the camera's [`CameraOutline`]. The material then takes the place of the
+/// camera's style.
+///
+/// # Shaders
+///
+/// The fragment shader is run over the view once the jump flood has
+/// finished, with the texture coordinates of the fragment at location 0:
+///
+/// ```wgsl
+/// #import outline::composite::{sample_outline, blend_output, globals}
+///
+/// @group(2) @binding(0)
+/// var<uniform> color: vec4<f32>;
+///
+/// @fragment
+/// fn fragment(@location(0) texcoord: vec2<f32>) -> @location(0) vec4<f32> {
+///     let outline = sample_outline(texcoord);
+///     let pulse = 0.5 + 0.5 * sin(globals.time * 4.0);
+///     let fade = clamp(1.0 - outline.distance / outline.width, 0.0, 1.0);
+///     return blend_output(color.rgb, fade * pulse * outline.intensity);
+/// }
+/// ```
+///
+/// The `outline::composite` import provides:
+///
+/// - `sample_outline(texcoord)`, which returns an `OutlineSample` with the
+///   distance to and position of the nearest seed on the mask edge, the mask
+///   coverage and object ID of the fragment, and the intensity, color override
+///   and width of the nearest outline, all in pixels where applicable.
+/// - `load_object_id(pixel)`, the object ID at a pixel of the mask.
+/// - `blend_output(color, alpha)`, which returns the output expected by the
+///   material's [`OutlineBlendMode`].
+/// - `globals`, Bevy's `Globals` uniform, including the elapsed `time`.
+///
+/// Meshes with an [`OutlineBlend`](crate::OutlineBlend) other than the
+/// material's blend mode are composited by another draw of the shader with
+/// their blend mode. Each draw samples an `intensity` of 0 near the meshes of
+/// the other draws.
+pub trait OutlineMaterial: Asset + AsBindGroup + Clone + Sized {
+    /// Returns the fragment shader of the composite pass.
+    ///
+    /// If [`ShaderRef::Default`], the built-in composite shader is used.
+    fn fragment_shader() -> ShaderRef {
+        ShaderRef::Default
+    }
+
+    /// Returns the width of the outline in pixels.
+    ///
+    /// Distances to the mask edge are computed up to twice this width.
+    fn width(&self) -> f32;
+
+    /// Returns how the composite is blended with the view target.
+    fn blend_mode(&self) -> OutlineBlendMode {
+        OutlineBlendMode::default()
+    }
+}
+
+/// Plugin for compositing outlines with a custom [`OutlineMaterial`].
+///
+/// Must be added after the [`OutlinePlugin`](crate::OutlinePlugin).
+pub struct OutlineMaterialPlugin<M: OutlineMaterial>(PhantomData<M>);
+
+impl<M: OutlineMaterial> Default for OutlineMaterialPlugin<M> {
+    fn default() -> Self {
+        OutlineMaterialPlugin(PhantomData)
+    }
+}
+
+impl<M: OutlineMaterial> Plugin for OutlineMaterialPlugin<M> {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<M>();
+
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            add_outline_material_systems::<M, ()>(render_app);
+            render_app.add_systems(ExtractSchedule, extract_camera_outline_materials::<M>);
+        }
+    }
+
+    fn finish(&self, app: &mut App) {
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            init_outline_material_resources::<M>(render_app);
+        }
+    }
+}
+
+/// Adds the render systems preparing an outline material for the views
+/// matching `F`.
+pub(crate) fn add_outline_material_systems<M, F>(render_app: &mut App)
+where
+    M: OutlineMaterial,
+    F: ReadOnlyWorldQuery + 'static,
+{
+    render_app
+        .init_resource::<ExtractedOutlineMaterials<M>>()
+        .init_resource::<RenderOutlineMaterials<M>>()
+        .add_systems(ExtractSchedule, extract_outline_materials::<M>)
+        .add_systems(
+            Render,
+            (
+                prepare_outline_materials::<M>
+                    .in_set(RenderSet::PrepareAssets)
+                    .after(prepare_assets::<Image>),
+                prepare_outline_composites::<M, F>
+                    .in_set(RenderSet::PrepareResources)
+                    .after(prepare_object_styles),
+            ),
+        );
+}
+
+pub(crate) fn init_outline_material_resources<M: OutlineMaterial>(render_app: &mut App) {
+    render_app
+        .init_resource::<OutlineMaterialPipeline<M>>()
+        .init_resource::<SpecializedRenderPipelines<OutlineMaterialPipeline<M>>>();
+}
+
+/// The composite pipeline of an outline material.
+#[derive(Resource)]
+pub struct OutlineMaterialPipeline<M: OutlineMaterial> {
+    outline: OutlinePipeline,
+    material_layout: BindGroupLayout,
+    fragment_shader: Option<Handle<Shader>>,
+    marker: PhantomData<M>,
+}
+
+impl<M: OutlineMaterial> FromWorld for OutlineMaterialPipeline<M> {
+    fn from_world(world: &mut World) -> Self {
+        let outline = world.resource::<OutlinePipeline>().clone();
+        let material_layout = M::bind_group_layout(world.resource::<RenderDevice>());
+        let fragment_shader = match M::fragment_shader() {
+            ShaderRef::Default => None,
+            ShaderRef::Handle(handle) => Some(handle),
+            ShaderRef::Path(path) => Some(world.resource::<AssetServer>().load(path)),
+        };
+
+        OutlineMaterialPipeline {
+            outline,
+            material_layout,
+            fragment_shader,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<M: OutlineMaterial> SpecializedRenderPipeline for OutlineMaterialPipeline<M> {
+    type Key = OutlinePipelineKey;
+
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        self.outline
+            .descriptor(key, &self.material_layout, self.fragment_shader.clone())
+    }
+}
+
+/// Outline materials extracted this frame.
+#[derive(Resource)]
+pub struct ExtractedOutlineMaterials<M: OutlineMaterial> {
+    extracted: Vec<(AssetId<M>, M)>,
+    removed: Vec<AssetId<M>>,
+}
+
+impl<M: OutlineMaterial> Default for ExtractedOutlineMaterials<M> {
+    fn default() -> Self {
+        ExtractedOutlineMaterials {
+            extracted: Vec::new(),
+            removed: Vec::new(),
+        }
+    }
+}
+
+/// An outline material prepared for the composite pass.
+pub struct PreparedOutlineMaterial {
+    pub(crate) width: f32,
+    pub(crate) blend_mode: OutlineBlendMode,
+    pub(crate) _bindings: Vec<OwnedBindingResource>,
+    pub(crate) bind_group: BindGroup,
+}
+
+/// Prepared outline materials of type `M`.
+#[derive(Resource, Deref, DerefMut)]
+pub struct RenderOutlineMaterials<M: OutlineMaterial>(HashMap<AssetId<M>, PreparedOutlineMaterial>);
+
+impl<M: OutlineMaterial> Default for RenderOutlineMaterials<M> {
+    fn default() -> Self {
+        RenderOutlineMaterials(HashMap::default())
+    }
+}
+
+/// The outline material of a view.
+#[derive(Component)]
+pub struct ViewOutlineMaterial<M: OutlineMaterial>(pub AssetId<M>);
+
+/// Marks views composited with a custom outline material rather than the
+/// style of their [`CameraOutline`].
+#[derive(Component)]
+pub struct CustomOutlineMaterial;
+
+/// The composite pass of an outlined view, from its outline material.
+#[derive(Component)]
+pub struct ViewOutlineComposite {
+    /// The width of the outline in pixels.
+    pub width: f32,
+    /// The widest outline of the view in pixels, including the width
+    /// overrides of outlined meshes.
+    pub max_width: f32,
+    // The pipeline of the material's blend mode first, then one for each
+    // other blend mode outlined meshes are overridden with.
+    pub(crate) pipelines: Vec<CachedRenderPipelineId>,
+    pub(crate) bind_group: BindGroup,
+}
+
+fn extract_outline_materials<M: OutlineMaterial>(
+    mut commands: Commands,
+    mut events: Extract<EventReader<AssetEvent<M>>>,
+    assets: Extract<Res<Assets<M>>>,
+) {
+    let mut changed = HashSet::default();
+    let mut removed = Vec::new();
+    for event in events.read() {
+        match event {
+            AssetEvent::Added { id } | AssetEvent::Modified { id } => {
+                changed.insert(*id);
+            }
+            AssetEvent::Removed { id } => {
+                changed.remove(id);
+                removed.push(*id);
+            }
+            AssetEvent::LoadedWithDependencies { .. } => {}
+        }
+    }
+
+    let extracted = changed
+        .into_iter()
+        .filter_map(|id| Some((id, assets.get(id)?.clone())))
+        .collect();
+
+    commands.insert_resource(ExtractedOutlineMaterials { extracted, removed });
+}
+
+type CameraOutlineMaterialQuery<'w, 's, M> =
+    Query<'w, 's, (Entity, &'static CameraOutline, &'static Handle<M>), With<Camera>>;
+
+fn extract_camera_outline_materials<M: OutlineMaterial>(
+    mut commands: Commands,
+    cameras: Extract<CameraOutlineMaterialQuery<M>>,
+) {
+    for (entity, outline, material) in cameras.iter() {
+        if outline.enabled {
+            commands
+                .get_or_spawn(entity)
+                .insert((ViewOutlineMaterial(material.id()), CustomOutlineMaterial));
+        }
+    }
+}
+
+fn prepare_outline_materials<M: OutlineMaterial>(
+    mut retry: Local<Vec<(AssetId<M>, M)>>,
+    mut extracted: ResMut<ExtractedOutlineMaterials<M>>,
+    mut materials: ResMut<RenderOutlineMaterials<M>>,
+    device: Res<RenderDevice>,
+    images: Res<RenderAssets<Image>>,
+    fallback_image: Res<FallbackImage>,
+    pipeline: Res<OutlineMaterialPipeline<M>>,
+) {
+    for removed in std::mem::take(&mut extracted.removed) {
+        materials.remove(&removed);
+    }
+
+    // Materials whose images weren't ready last frame are retried first.
+    let queued = std::mem::take(&mut *retry);
+    for (id, material) in queued
+        .into_iter()
+        .chain(std::mem::take(&mut extracted.extracted))
+    {
+        match material.as_bind_group(&pipeline.material_layout, &device, &images, &fallback_image) {
+            Ok(prepared) => {
+                materials.insert(
+                    id,
+                    PreparedOutlineMaterial {
+                        width: material.width(),
+                        blend_mode: material.blend_mode(),
+                        _bindings: prepared
+                            .bindings
+                            .into_iter()
+                            .map(|(_, binding)| binding)
+                            .collect(),
+                        bind_group: prepared.bind_group,
+                    },
+                );
+            }
+            Err(AsBindGroupError::RetryNextUpdate) => retry.push((id, material)),
+        }
+    }
+}
+
+/// Specializes the composite pipeline of each view using an outline material
+/// of type `M`.
+#[allow(clippy::too_many_arguments)]
+fn prepare_outline_composites<M, F>(
+    mut commands: Commands,
+    pipeline_cache: Res<PipelineCache>,
+    mut pipelines: ResMut<SpecializedRenderPipelines<OutlineMaterialPipeline<M>>>,
+    material_pipeline: Res<OutlineMaterialPipeline<M>>,
+    settings: Res<OutlineSettings>,
+    outline_res: Res<OutlineResources>,
+    materials: Res<RenderOutlineMaterials<M>>,
+    views: Query<(Entity, &ViewTarget, &ViewOutlineMaterial<M>), F>,
+) where
+    M: OutlineMaterial,
+    F: ReadOnlyWorldQuery + 'static,
+{
+    for (entity, target, material) in views.iter() {
+        // The material may still be loading.
+        let Some(material) = materials.get(&material.0) else {
+            continue;
+        };
+
+        // The composite pass draws into the main texture of the view.
+        let key = OutlinePipelineKey::new(target.main_texture_format())
+            .expect("invalid format for OutlineNode")
+            .with_blend_mode(material.blend_mode)
+            .with_debug_mode(settings.debug_mode)
+            .with_mask_multisampled(outline_res.mask_sample_count > 1);
+        let mut view_pipelines =
+            vec![pipelines.specialize(&pipeline_cache, &material_pipeline, key)];
+        // Debug visualizations ignore blend modes.
+        if settings.debug_mode.is_none() {
+            for &blend_mode in &outline_res.object_blend_modes {
+                if blend_mode != material.blend_mode {
+                    let key = key.with_blend_mode(blend_mode).with_object_blend_pass(true);
+                    view_pipelines.push(pipelines.specialize(
+                        &pipeline_cache,
+                        &material_pipeline,
+                        key,
+                    ));
+                }
+            }
+        }
+
+        commands.entity(entity).insert(ViewOutlineComposite {
+            width: material.width,
+            max_width: material.width.max(outline_res.object_max_width),
+            pipelines: view_pipelines,
+            bind_group: material.bind_group.clone(),
+        });
+    }
+}
@@ -1,24 +1,25 @@
 use bevy::{
     prelude::*,
     render::{
-        render_asset::RenderAssets,
         render_graph::{Node, NodeRunError, RenderGraphContext, SlotInfo, SlotType},
         render_resource::{
-            BindGroup, BindGroupLayout, BlendComponent, BlendFactor, BlendOperation, BlendState,
-            CachedRenderPipelineId, ColorTargetState, ColorWrites, FragmentState, LoadOp,
-            MultisampleState, Operations, PipelineCache, RenderPassColorAttachment,
-            RenderPassDescriptor, RenderPipelineDescriptor, ShaderType, SpecializedRenderPipeline,
-            SpecializedRenderPipelines, TextureFormat, TextureSampleType, TextureUsages,
-            UniformBuffer, VertexState,
+            BindGroupLayout, BlendComponent, BlendFactor, BlendOperation, BlendState,
+            ColorTargetState, ColorWrites, FragmentState, LoadOp, MultisampleState, Operations,
+            PipelineCache, RenderPassColorAttachment, RenderPassDescriptor,
+            RenderPipelineDescriptor, ShaderDefVal, ShaderType, TextureFormat, TextureSampleType,
+            TextureUsages, VertexState,
         },
         renderer::RenderContext,
         view::ViewTarget,
     },
 };
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    resources::{self, OutlineResources},
-    CameraOutline, OutlineStyle, FULLSCREEN_PRIMITIVE_STATE, OUTLINE_SHADER_HANDLE,
+    diagnostics::{OutlineStage, OutlineStats},
+    material::ViewOutlineComposite,
+    resources::{self, OutlineResources, ViewOutlineDimensions},
+    ViewOutlineBounds, FULLSCREEN_PRIMITIVE_STATE, OUTLINE_SHADER_HANDLE,
 };
 
 #[derive(Clone, Debug, Default, PartialEq, ShaderType)]
@@ -36,21 +37,19 @@ impl OutlineParams {
         let color: Vec4 = color.as_rgba_f32().into();
         let inner_color: Vec4 = inner_color.as_rgba_f32().into();
 
-        OutlineParams { color, inner_color, weight }
+        OutlineParams {
+            color,
+            inner_color,
+            weight,
+        }
     }
 }
 
-pub struct GpuOutlineParams {
-    pub(crate) params: OutlineParams,
-    pub(crate) _buffer: UniformBuffer<OutlineParams>,
-    pub(crate) bind_group: BindGroup,
-}
-
 #[derive(Clone, Debug, Resource)]
 pub struct OutlinePipeline {
     dimensions_layout: BindGroupLayout,
     input_layout: BindGroupLayout,
-    params_layout: BindGroupLayout,
+    single_sample_input_layout: BindGroupLayout,
 }
 
 impl FromWorld for OutlinePipeline {
@@ -58,12 +57,98 @@ impl FromWorld for OutlinePipeline {
         let res = world.get_resource::<resources::OutlineResources>().unwrap();
         let dimensions_layout = res.dimensions_bind_group_layout.clone();
         let input_layout = res.outline_src_bind_group_layout.clone();
-        let params_layout = res.outline_params_bind_group_layout.clone();
+        let single_sample_input_layout = res.outline_src_single_sample_bind_group_layout.clone();
 
         OutlinePipeline {
             dimensions_layout,
             input_layout,
-            params_layout,
+            single_sample_input_layout,
+        }
+    }
+}
+
+/// Visualizations of intermediate outline results, for debugging.
+///
+/// When enabled, the outline composite pass is replaced by an opaque
+/// visualization of the selected stage.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Reflect)]
+pub enum OutlineDebugMode {
+    /// The coverage of the mask pass, in grayscale.
+    Mask,
+    /// Object IDs from the mask pass, in false color.
+    ObjectIds,
+    /// Seed pixels produced by the JFA initialization pass.
+    Seeds,
+    /// The Voronoi diagram of the seeds, colored by nearest seed.
+    Voronoi,
+    /// The distance to the nearest seed, as a heatmap over twice the outline
+    /// width.
+    Distance,
+}
+
+impl OutlineDebugMode {
+    fn shader_def(&self) -> &'static str {
+        match self {
+            OutlineDebugMode::Mask => "DEBUG_MASK",
+            OutlineDebugMode::ObjectIds => "DEBUG_OBJECT_IDS",
+            OutlineDebugMode::Seeds => "DEBUG_SEEDS",
+            OutlineDebugMode::Voronoi => "DEBUG_VORONOI",
+            OutlineDebugMode::Distance => "DEBUG_DISTANCE",
+        }
+    }
+}
+
+/// How an outline is blended with the view target.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Reflect, Serialize, Deserialize)]
+#[reflect(Serialize, Deserialize)]
+pub enum OutlineBlendMode {
+    /// Blends the outline over the scene using its alpha.
+    #[default]
+    Alpha,
+    /// Adds the outline color, weighted by its alpha, to the scene.
+    Additive,
+    /// Multiplies the scene by the outline color, weighted by its alpha.
+    Multiply,
+}
+
+impl OutlineBlendMode {
+    /// Returns the index of the blend mode in shaders, where 0 stands for no
+    /// blend mode.
+    pub(crate) fn shader_index(&self) -> u32 {
+        match self {
+            OutlineBlendMode::Alpha => 1,
+            OutlineBlendMode::Additive => 2,
+            OutlineBlendMode::Multiply => 3,
+        }
+    }
+
+    fn blend_state(&self) -> BlendState {
+        let color = match self {
+            OutlineBlendMode::Alpha => BlendComponent {
+                src_factor: BlendFactor::SrcAlpha,
+                dst_factor: BlendFactor::OneMinusSrcAlpha,
+                operation: BlendOperation::Add,
+            },
+            OutlineBlendMode::Additive => BlendComponent {
+                src_factor: BlendFactor::SrcAlpha,
+                dst_factor: BlendFactor::One,
+                operation: BlendOperation::Add,
+            },
+            // The shader premultiplies the color for this mode.
+            OutlineBlendMode::Multiply => BlendComponent {
+                src_factor: BlendFactor::Dst,
+                dst_factor: BlendFactor::OneMinusSrcAlpha,
+                operation: BlendOperation::Add,
+            },
+        };
+
+        BlendState {
+            color,
+            alpha: BlendComponent {
+                src_factor: BlendFactor::One,
+                dst_factor: BlendFactor::Zero,
+                operation: BlendOperation::Max,
+            },
         }
     }
 }
@@ -71,6 +156,10 @@ impl FromWorld for OutlinePipeline {
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct OutlinePipelineKey {
     format: TextureFormat,
+    blend_mode: OutlineBlendMode,
+    debug_mode: Option<OutlineDebugMode>,
+    mask_multisampled: bool,
+    object_blend_pass: bool,
 }
 
 impl OutlinePipelineKey {
@@ -85,50 +174,108 @@ impl OutlinePipelineKey {
             .allowed_usages
             .contains(TextureUsages::RENDER_ATTACHMENT)
         {
-            Some(OutlinePipelineKey { format })
+            Some(OutlinePipelineKey {
+                format,
+                blend_mode: OutlineBlendMode::default(),
+                debug_mode: None,
+                mask_multisampled: true,
+                object_blend_pass: false,
+            })
         } else {
             None
         }
     }
 }
 
-impl SpecializedRenderPipeline for OutlinePipeline {
-    type Key = OutlinePipelineKey;
+impl OutlinePipelineKey {
+    pub fn with_blend_mode(self, blend_mode: OutlineBlendMode) -> OutlinePipelineKey {
+        OutlinePipelineKey { blend_mode, ..self }
+    }
 
-    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
-        let blend = BlendState {
-            color: BlendComponent {
-                src_factor: BlendFactor::SrcAlpha,
-                dst_factor: BlendFactor::OneMinusSrcAlpha,
-                operation: BlendOperation::Add,
-            },
-            alpha: BlendComponent {
-                src_factor: BlendFactor::One,
-                dst_factor: BlendFactor::Zero,
-                operation: BlendOperation::Max,
-            },
+    pub fn with_debug_mode(self, debug_mode: Option<OutlineDebugMode>) -> OutlinePipelineKey {
+        OutlinePipelineKey { debug_mode, ..self }
+    }
+
+    pub fn with_mask_multisampled(self, mask_multisampled: bool) -> OutlinePipelineKey {
+        OutlinePipelineKey {
+            mask_multisampled,
+            ..self
+        }
+    }
+
+    /// Draws only the outlines of objects overriding their blend mode with
+    /// the blend mode of the key.
+    pub fn with_object_blend_pass(self, object_blend_pass: bool) -> OutlinePipelineKey {
+        OutlinePipelineKey {
+            object_blend_pass,
+            ..self
+        }
+    }
+}
+
+impl OutlinePipeline {
+    /// Returns the composite pipeline for an outline material with the given
+    /// bind group layout and fragment shader.
+    ///
+    /// Without a fragment shader, the built-in composite is used. Debug
+    /// visualizations always use the built-in shader.
+    pub(crate) fn descriptor(
+        &self,
+        key: OutlinePipelineKey,
+        material_layout: &BindGroupLayout,
+        fragment_shader: Option<Handle<Shader>>,
+    ) -> RenderPipelineDescriptor {
+        // Debug visualizations are opaque.
+        let blend = match key.debug_mode {
+            Some(_) => OutlineBlendMode::Alpha,
+            None => key.blend_mode,
         };
 
+        let mut shader_defs = vec![ShaderDefVal::UInt(
+            "OUTLINE_BLEND_MODE".into(),
+            blend.shader_index(),
+        )];
+        if key.object_blend_pass {
+            shader_defs.push("OBJECT_BLEND_PASS".into());
+        }
+        if blend == OutlineBlendMode::Multiply {
+            shader_defs.push("BLEND_MULTIPLY".into());
+        }
+        let input_layout = if key.mask_multisampled {
+            shader_defs.push("MASK_MULTISAMPLED".into());
+            &self.input_layout
+        } else {
+            &self.single_sample_input_layout
+        };
+        let mut entry_point = "fragment";
+        let mut fragment_shader = fragment_shader.unwrap_or(OUTLINE_SHADER_HANDLE);
+        if let Some(debug_mode) = key.debug_mode {
+            shader_defs.push("OUTLINE_DEBUG".into());
+            shader_defs.push(debug_mode.shader_def().into());
+            entry_point = "debug_fragment";
+            fragment_shader = OUTLINE_SHADER_HANDLE;
+        }
+
         RenderPipelineDescriptor {
             label: Some("jfa_outline_pipeline".into()),
             layout: vec![
                 self.dimensions_layout.clone(),
-                self.input_layout.clone(),
-                self.params_layout.clone(),
+                input_layout.clone(),
+                material_layout.clone(),
             ],
             vertex: VertexState {
-                shader: OUTLINE_SHADER_HANDLE.typed::<Shader>(),
+                shader: OUTLINE_SHADER_HANDLE,
                 shader_defs: vec![],
                 entry_point: "vertex".into(),
                 buffers: vec![],
             },
             fragment: Some(FragmentState {
-                shader: OUTLINE_SHADER_HANDLE.typed::<Shader>(),
-                shader_defs: vec![],
-                entry_point: "fragment".into(),
+                shader: fragment_shader,
+                shader_defs,
+                entry_point: entry_point.into(),
                 targets: vec![Some(ColorTargetState {
                     format: key.format,
-                    blend: Some(blend),
+                    blend: Some(blend.blend_state()),
                     write_mask: ColorWrites::ALL,
                 })],
             }),
@@ -145,38 +292,29 @@ impl SpecializedRenderPipeline for OutlinePipeline {
 }
 
 pub struct OutlineNode {
-    pipeline_id: CachedRenderPipelineId,
-    query: QueryState<(&'static CameraOutline, &'static ViewTarget)>,
+    query: QueryState<(
+        &'static ViewTarget,
+        &'static ViewOutlineComposite,
+        &'static ViewOutlineDimensions,
+    )>,
 }
 
 impl OutlineNode {
     pub const IN_JFA: &'static str = "in_jfa";
 
-    pub fn new(world: &mut World, target_format: TextureFormat) -> OutlineNode {
-        let pipeline_id = world.resource_scope(|world, mut cache: Mut<PipelineCache>| {
-            let base = world.get_resource::<OutlinePipeline>().unwrap().clone();
-            let mut spec = world
-                .get_resource_mut::<SpecializedRenderPipelines<OutlinePipeline>>()
-                .unwrap();
-            let key =
-                OutlinePipelineKey::new(target_format).expect("invalid format for OutlineNode");
-            spec.specialize(&mut cache, &base, key)
-        });
-
+    pub fn new(world: &mut World) -> OutlineNode {
         let query = QueryState::new(world);
 
-        OutlineNode { pipeline_id, query }
+        OutlineNode { query }
     }
 }
 
 impl Node for OutlineNode {
     fn input(&self) -> Vec<SlotInfo> {
-        vec![
-            SlotInfo {
-                name: Self::IN_JFA.into(),
-                slot_type: SlotType::TextureView,
-            },
-        ]
+        vec![SlotInfo {
+            name: Self::IN_JFA.into(),
+            slot_type: SlotType::TextureView,
+        }]
     }
 
     fn output(&self) -> Vec<SlotInfo> {
@@ -194,17 +332,22 @@ impl Node for OutlineNode {
         world: &World,
     ) -> Result<(), NodeRunError> {
         let view_ent = graph.get_view_entity().unwrap();
-        if let Ok((outline, target)) = self.query.get_manual(world, view_ent) {
-            let styles = world.resource::<RenderAssets<OutlineStyle>>();
-            let style = styles.get(&outline.style).unwrap();
-
+        if let Ok((target, composite, dims)) = self.query.get_manual(world, view_ent) {
             let res = world.get_resource::<OutlineResources>().unwrap();
+            let target_res = res.view_target(world, view_ent).unwrap();
 
-            let pipelines = world.get_resource::<PipelineCache>().unwrap();
-            let pipeline = match pipelines.get_render_pipeline(self.pipeline_id) {
-                Some(p) => p,
-                None => return Ok(()),
-            };
+            let pipeline_cache = world.get_resource::<PipelineCache>().unwrap();
+            let pipelines: Vec<_> = composite
+                .pipelines
+                .iter()
+                .filter_map(|&id| pipeline_cache.get_render_pipeline(id))
+                .collect();
+            if pipelines.is_empty() {
+                return Ok(());
+            }
+
+            let stats = world.resource::<OutlineStats>();
+            stats.begin_stage(OutlineStage::Composite, render_context);
 
             let mut tracked_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
                 label: Some("jfa_outline"),
@@ -220,11 +363,22 @@ impl Node for OutlineNode {
                 depth_stencil_attachment: None,
             });
 
-            tracked_pass.set_render_pipeline(pipeline);
-            tracked_pass.set_bind_group(0, &res.dimensions_bind_group, &[]);
-            tracked_pass.set_bind_group(1, &res.outline_src_bind_group, &[]);
-            tracked_pass.set_bind_group(2, &style.bind_group, &[]);
-            tracked_pass.draw(0..3, 0..1);
+            if let Some(bounds) = world.get::<ViewOutlineBounds>(view_ent) {
+                bounds.set_scissor(&mut tracked_pass);
+            }
+            // Outlines overriding the blend mode are drawn by the pipelines
+            // after the first, one per blend mode.
+            for pipeline in pipelines {
+                tracked_pass.set_render_pipeline(pipeline);
+                tracked_pass.set_bind_group(0, &res.dimensions_bind_group, &[dims.offset]);
+                tracked_pass.set_bind_group(1, &target_res.outline_src_bind_group, &[]);
+                tracked_pass.set_bind_group(2, &composite.bind_group, &[]);
+                tracked_pass.draw(0..3, 0..1);
+            }
+            drop(tracked_pass);
+
+            stats.pass_recorded(OutlineStage::Composite);
+            stats.end_stage(render_context);
         }
 
         Ok(())
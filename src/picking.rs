@@ -0,0 +1,777 @@
+use bevy::{
+    ecs::system::EntityCommands,
+    math::Vec3A,
+    prelude::*,
+    render::{
+        camera::NormalizedRenderTarget,
+        mesh::{Indices, PrimitiveTopology, VertexAttributeValues},
+        primitives::Aabb,
+    },
+    window::PrimaryWindow,
+};
+
+use crate::{
+    outline::OutlineBlendMode, CameraOutline, InheritedOutline, Outline, OutlineBlend,
+    OutlineColor, OutlineIntensity, OutlineOverrides, OutlineWidth,
+};
+
+/// Plugin for outlining entities when they are hovered or selected with the
+/// mouse.
+///
+/// Entities with an [`OutlineOnHover`] are outlined while the cursor is over
+/// them, and entities with an [`OutlineOnSelect`] are outlined after being
+/// clicked, until something else or empty space is clicked. Their
+/// [`Outline::enabled`] is managed by this plugin, and an [`Outline`] is
+/// inserted if they don't have one. The picking colors, widths and blend modes
+/// of the [`OutlinePickingSettings`] are applied to them as an
+/// [`OutlineColor`], [`OutlineWidth`] and [`OutlineBlend`], replacing their
+/// own until they are no longer picked and their outline has faded out.
+///
+/// The cursor is cast as a ray from every active camera with a
+/// [`CameraOutline`]. Meshes are tested against their `Aabb` first, then
+/// against their triangles. Meshes in an [`OutlineHierarchy`](crate::OutlineHierarchy)
+/// are picked as the hierarchy's root.
+///
+/// Requires the [`OutlinePlugin`](crate::OutlinePlugin).
+#[derive(Default)]
+pub struct OutlinePickingPlugin;
+
+impl Plugin for OutlinePickingPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<OutlineOnHover>()
+            .register_type::<OutlineOnSelect>()
+            .init_resource::<OutlinePickingSettings>()
+            .add_event::<OutlineHovered>()
+            .add_event::<OutlineSelected>()
+            .add_systems(
+                Update,
+                (pick_outlines, apply_picked_outlines, apply_picking_styles).chain(),
+            );
+    }
+}
+
+/// Settings for the [`OutlinePickingPlugin`].
+///
+/// Overrides are applied per entity, so hovered and selected entities are
+/// outlined in their own styles at the same time. Those of selected entities
+/// take precedence over those of hovered entities. Where an override is
+/// `None`, the entity's own override or the camera's [`OutlineStyle`] is used.
+///
+/// [`OutlineStyle`]: crate::OutlineStyle
+#[derive(Clone, Debug, Resource)]
+pub struct OutlinePickingSettings {
+    /// Outline color of hovered entities.
+    pub hover_color: Option<Color>,
+    /// Outline color of selected entities.
+    pub select_color: Option<Color>,
+    /// Outline width of hovered entities, in pixels.
+    pub hover_width: Option<f32>,
+    /// Outline width of selected entities, in pixels.
+    pub select_width: Option<f32>,
+    /// Blend mode of the outlines of hovered entities.
+    pub hover_blend_mode: Option<OutlineBlendMode>,
+    /// Blend mode of the outlines of selected entities.
+    pub select_blend_mode: Option<OutlineBlendMode>,
+    /// Mouse button used to select entities.
+    pub select_button: MouseButton,
+}
+
+impl Default for OutlinePickingSettings {
+    fn default() -> Self {
+        OutlinePickingSettings {
+            hover_color: None,
+            select_color: None,
+            hover_width: None,
+            select_width: None,
+            hover_blend_mode: None,
+            select_blend_mode: None,
+            select_button: MouseButton::Left,
+        }
+    }
+}
+
+/// Component for outlining an entity while the cursor is over it.
+///
+/// See [`OutlinePickingPlugin`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Component, Reflect)]
+#[reflect(Component, Default)]
+pub struct OutlineOnHover {
+    hovered: bool,
+}
+
+impl OutlineOnHover {
+    /// Returns whether the cursor is over the entity.
+    pub fn is_hovered(&self) -> bool {
+        self.hovered
+    }
+}
+
+/// Component for outlining an entity after it has been clicked.
+///
+/// See [`OutlinePickingPlugin`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Component, Reflect)]
+#[reflect(Component, Default)]
+pub struct OutlineOnSelect {
+    selected: bool,
+}
+
+impl OutlineOnSelect {
+    /// Returns whether the entity is selected.
+    pub fn is_selected(&self) -> bool {
+        self.selected
+    }
+}
+
+/// Event sent when the cursor enters or leaves an entity with an
+/// [`OutlineOnHover`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Event)]
+pub struct OutlineHovered {
+    pub entity: Entity,
+    pub hovered: bool,
+}
+
+/// Event sent when an entity with an [`OutlineOnSelect`] is selected or
+/// deselected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Event)]
+pub struct OutlineSelected {
+    pub entity: Entity,
+    pub selected: bool,
+}
+
+type PickableMeshQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static Handle<Mesh>,
+        &'static GlobalTransform,
+        Option<&'static Aabb>,
+        Option<&'static InheritedOutline>,
+        Option<&'static ViewVisibility>,
+    ),
+    Or<(
+        With<OutlineOnHover>,
+        With<OutlineOnSelect>,
+        With<InheritedOutline>,
+    )>,
+>;
+
+#[allow(clippy::too_many_arguments)]
+fn pick_outlines(
+    settings: Res<OutlinePickingSettings>,
+    mouse: Res<Input<MouseButton>>,
+    primary_window: Query<Entity, With<PrimaryWindow>>,
+    windows: Query<(Entity, &Window)>,
+    cameras: Query<(&Camera, &GlobalTransform), With<CameraOutline>>,
+    meshes: Res<Assets<Mesh>>,
+    pickable: PickableMeshQuery,
+    mut hovers: Query<(Entity, &mut OutlineOnHover)>,
+    mut selects: Query<(Entity, &mut OutlineOnSelect)>,
+    mut hovered_events: EventWriter<OutlineHovered>,
+    mut selected_events: EventWriter<OutlineSelected>,
+) {
+    let primary_window = primary_window.get_single().ok();
+
+    let mut rays = Vec::new();
+    for (window_entity, window) in windows.iter() {
+        let Some(cursor) = window.cursor_position() else {
+            continue;
+        };
+        // Only the topmost camera under the cursor is picked from.
+        let camera = cameras
+            .iter()
+            .filter(|(camera, _)| {
+                let target = camera.target.normalize(primary_window);
+                camera.is_active
+                    && matches!(target, Some(NormalizedRenderTarget::Window(window))
+                        if window.entity() == window_entity)
+            })
+            .filter(|(camera, _)| {
+                camera
+                    .logical_viewport_rect()
+                    .is_some_and(|rect| rect.contains(cursor))
+            })
+            .max_by_key(|(camera, _)| camera.order);
+        let Some((camera, camera_transform)) = camera else {
+            continue;
+        };
+
+        let viewport_min = camera.logical_viewport_rect().unwrap().min;
+        if let Some(ray) = camera.viewport_to_world(camera_transform, cursor - viewport_min) {
+            rays.push(ray);
+        }
+    }
+
+    let mut nearest: Option<(f32, Entity)> = None;
+    for (entity, mesh, transform, aabb, inherited, visibility) in pickable.iter() {
+        if visibility.is_some_and(|visibility| !visibility.get()) {
+            continue;
+        }
+        let Some(mesh) = meshes.get(mesh) else {
+            continue;
+        };
+
+        let target = if hovers.contains(entity) || selects.contains(entity) {
+            entity
+        } else {
+            match inherited {
+                Some(inherited) => inherited.root(),
+                None => continue,
+            }
+        };
+
+        for ray in rays.iter() {
+            let Some(distance) = ray_mesh_distance(*ray, transform, aabb, mesh) else {
+                continue;
+            };
+            if nearest.is_none_or(|(nearest, _)| distance < nearest) {
+                nearest = Some((distance, target));
+            }
+        }
+    }
+    let picked = nearest.map(|(_, entity)| entity);
+
+    for (entity, mut hover) in hovers.iter_mut() {
+        let hovered = picked == Some(entity);
+        if hover.hovered != hovered {
+            hover.hovered = hovered;
+            hovered_events.send(OutlineHovered { entity, hovered });
+        }
+    }
+
+    if mouse.just_pressed(settings.select_button) {
+        for (entity, mut select) in selects.iter_mut() {
+            let selected = picked == Some(entity);
+            if select.selected != selected {
+                select.selected = selected;
+                selected_events.send(OutlineSelected { entity, selected });
+            }
+        }
+    }
+}
+
+type PickedOutlineQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        Option<&'static OutlineOnHover>,
+        Option<&'static OutlineOnSelect>,
+        Option<&'static mut Outline>,
+    ),
+    Or<(Changed<OutlineOnHover>, Changed<OutlineOnSelect>)>,
+>;
+
+fn apply_picked_outlines(mut commands: Commands, mut outlines: PickedOutlineQuery) {
+    for (entity, hover, select, outline) in outlines.iter_mut() {
+        let enabled = hover.is_some_and(OutlineOnHover::is_hovered)
+            || select.is_some_and(OutlineOnSelect::is_selected);
+        match outline {
+            Some(mut outline) => {
+                if outline.enabled != enabled {
+                    outline.enabled = enabled;
+                }
+            }
+            None => {
+                commands.entity(entity).insert(Outline { enabled });
+            }
+        }
+    }
+}
+
+/// An outline override applied to a picked entity, and the entity's own
+/// override it replaced.
+struct PickingOverride<T> {
+    applied: Option<T>,
+    saved: Option<T>,
+}
+
+impl<T> Default for PickingOverride<T> {
+    fn default() -> Self {
+        PickingOverride {
+            applied: None,
+            saved: None,
+        }
+    }
+}
+
+impl<T: Component + Copy + PartialEq> PickingOverride<T> {
+    /// Applies the override of a pick state, or restores the entity's own
+    /// override if the pick state has none.
+    fn apply(&mut self, entity: &mut EntityCommands, current: Option<&T>, value: Option<T>) {
+        let Some(value) = value else {
+            self.restore(entity, current);
+            return;
+        };
+
+        // Anything but the applied override is the entity's own, including
+        // overrides changed while the entity is picked.
+        if current != self.applied.as_ref() {
+            self.saved = current.copied();
+        }
+        if current != Some(&value) {
+            entity.insert(value);
+        }
+        self.applied = Some(value);
+    }
+
+    /// Restores the override the entity had before picking applied one.
+    fn restore(&mut self, entity: &mut EntityCommands, current: Option<&T>) {
+        let (Some(applied), saved) = (self.applied.take(), self.saved.take()) else {
+            return;
+        };
+        // Overrides given to the entity since then are kept.
+        if current != Some(&applied) {
+            return;
+        }
+        match saved {
+            Some(saved) => entity.insert(saved),
+            None => entity.remove::<T>(),
+        };
+    }
+}
+
+/// Outline overrides applied to a picked entity, restored once the entity is
+/// no longer picked.
+#[derive(Component, Default)]
+struct PickingOverrides {
+    color: PickingOverride<OutlineColor>,
+    width: PickingOverride<OutlineWidth>,
+    blend: PickingOverride<OutlineBlend>,
+}
+
+type PickedStyleQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        Option<&'static OutlineOnHover>,
+        Option<&'static OutlineOnSelect>,
+        OutlineOverrides,
+        Option<&'static mut PickingOverrides>,
+        Option<&'static OutlineIntensity>,
+    ),
+    Or<(With<OutlineOnHover>, With<OutlineOnSelect>)>,
+>;
+
+/// Applies the picking overrides to the entities which are hovered or
+/// selected, and restores their own overrides when they no longer are.
+///
+/// Entities keep the picking overrides while their outline fades out.
+/// Overrides given to an entity while it is picked are restored in the same
+/// way.
+fn apply_picking_styles(
+    mut commands: Commands,
+    settings: Res<OutlinePickingSettings>,
+    mut picked: PickedStyleQuery,
+) {
+    for (entity, hover, select, (color, width, blend), overrides, intensity) in picked.iter_mut() {
+        let style = if select.is_some_and(OutlineOnSelect::is_selected) {
+            Some((
+                settings.select_color,
+                settings.select_width,
+                settings.select_blend_mode,
+            ))
+        } else if hover.is_some_and(OutlineOnHover::is_hovered) {
+            Some((
+                settings.hover_color,
+                settings.hover_width,
+                settings.hover_blend_mode,
+            ))
+        } else {
+            None
+        };
+
+        let Some((picked_color, picked_width, picked_blend)) = style else {
+            let fading = intensity.is_some_and(|intensity| intensity.current() > 0.0);
+            if let Some(mut overrides) = overrides.filter(|_| !fading) {
+                let mut entity = commands.entity(entity);
+                overrides.color.restore(&mut entity, color);
+                overrides.width.restore(&mut entity, width);
+                overrides.blend.restore(&mut entity, blend);
+                entity.remove::<PickingOverrides>();
+            }
+            continue;
+        };
+
+        let mut entity = commands.entity(entity);
+        let mut inserted = None;
+        let overrides = match overrides {
+            Some(overrides) => overrides.into_inner(),
+            None => inserted.insert(PickingOverrides::default()),
+        };
+        // Without a picking override, the entity shows its own.
+        overrides
+            .color
+            .apply(&mut entity, color, picked_color.map(OutlineColor));
+        overrides
+            .width
+            .apply(&mut entity, width, picked_width.map(OutlineWidth));
+        overrides
+            .blend
+            .apply(&mut entity, blend, picked_blend.map(OutlineBlend));
+        if let Some(overrides) = inserted {
+            entity.insert(overrides);
+        }
+    }
+}
+
+/// Returns the distance along `ray` to the nearest triangle of `mesh`.
+///
+/// Meshes that aren't triangle lists are tested against their bounding box
+/// only.
+fn ray_mesh_distance(
+    ray: Ray,
+    transform: &GlobalTransform,
+    aabb: Option<&Aabb>,
+    mesh: &Mesh,
+) -> Option<f32> {
+    // The direction isn't normalized after the transform, so distances along
+    // the local ray are the same as along the world ray.
+    let world_to_local = transform.compute_matrix().inverse();
+    let origin = world_to_local.transform_point3(ray.origin);
+    let direction = world_to_local.transform_vector3(ray.direction);
+
+    let aabb_distance = match aabb {
+        Some(aabb) => Some(ray_aabb_distance(origin, direction, aabb)?),
+        None => None,
+    };
+
+    if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+        return aabb_distance;
+    }
+    let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        return aabb_distance;
+    };
+
+    let triangle = |[a, b, c]: [usize; 3]| {
+        let vertex = |i: usize| positions.get(i).copied().map(Vec3::from);
+        ray_triangle_distance(origin, direction, [vertex(a)?, vertex(b)?, vertex(c)?])
+    };
+    let nearest =
+        |distances: &mut dyn Iterator<Item = f32>| distances.min_by(|a, b| a.total_cmp(b));
+
+    match mesh.indices() {
+        Some(Indices::U16(indices)) => nearest(
+            &mut indices
+                .chunks_exact(3)
+                .filter_map(|tri| triangle([tri[0] as usize, tri[1] as usize, tri[2] as usize])),
+        ),
+        Some(Indices::U32(indices)) => nearest(
+            &mut indices
+                .chunks_exact(3)
+                .filter_map(|tri| triangle([tri[0] as usize, tri[1] as usize, tri[2] as usize])),
+        ),
+        None => nearest(
+            &mut (0..positions.len() / 3).filter_map(|i| triangle([3 * i, 3 * i + 1, 3 * i + 2])),
+        ),
+    }
+}
+
+/// Returns the distance along a ray to where it enters `aabb`, or 0 if it
+/// starts inside.
+fn ray_aabb_distance(origin: Vec3, direction: Vec3, aabb: &Aabb) -> Option<f32> {
+    let inv_direction = Vec3A::from(direction).recip();
+    let t0 = (aabb.min() - Vec3A::from(origin)) * inv_direction;
+    let t1 = (aabb.max() - Vec3A::from(origin)) * inv_direction;
+
+    let near = t0.min(t1).max_element().max(0.0);
+    let far = t0.max(t1).min_element();
+    (near <= far).then_some(near)
+}
+
+/// Returns the distance along a ray to a triangle, using the Möller–Trumbore
+/// algorithm. Both faces of the triangle are hit.
+fn ray_triangle_distance(origin: Vec3, direction: Vec3, [a, b, c]: [Vec3; 3]) -> Option<f32> {
+    let edge_ab = b - a;
+    let edge_ac = c - a;
+    let p = direction.cross(edge_ac);
+    let det = edge_ab.dot(p);
+    if det.abs() < f32::EPSILON {
+        return None;
+    }
+
+    let inv_det = det.recip();
+    let to_origin = origin - a;
+    let u = to_origin.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = to_origin.cross(edge_ab);
+    let v = direction.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = edge_ac.dot(q) * inv_det;
+    (t >= 0.0).then_some(t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ray_hits_aabb() {
+        let aabb = Aabb::from_min_max(Vec3::splat(-1.0), Vec3::splat(1.0));
+
+        let distance = ray_aabb_distance(Vec3::new(0.0, 0.0, 5.0), Vec3::NEG_Z, &aabb);
+        assert_eq!(distance, Some(4.0));
+
+        let inside = ray_aabb_distance(Vec3::ZERO, Vec3::X, &aabb);
+        assert_eq!(inside, Some(0.0));
+
+        let miss = ray_aabb_distance(Vec3::new(2.0, 0.0, 5.0), Vec3::NEG_Z, &aabb);
+        assert_eq!(miss, None);
+
+        let behind = ray_aabb_distance(Vec3::new(0.0, 0.0, 5.0), Vec3::Z, &aabb);
+        assert_eq!(behind, None);
+    }
+
+    #[test]
+    fn ray_hits_triangle() {
+        let triangle = [
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ];
+
+        let front = ray_triangle_distance(Vec3::new(0.0, 0.0, 3.0), Vec3::NEG_Z, triangle);
+        assert_eq!(front, Some(3.0));
+
+        let back = ray_triangle_distance(Vec3::new(0.0, 0.0, -2.0), Vec3::Z, triangle);
+        assert_eq!(back, Some(2.0));
+
+        let miss = ray_triangle_distance(Vec3::new(0.9, 0.9, 3.0), Vec3::NEG_Z, triangle);
+        assert_eq!(miss, None);
+
+        let parallel = ray_triangle_distance(Vec3::new(0.0, 0.0, 3.0), Vec3::X, triangle);
+        assert_eq!(parallel, None);
+    }
+
+    #[test]
+    fn ray_hits_scaled_mesh() {
+        let mesh = Mesh::from(shape::Cube { size: 2.0 });
+        let aabb = mesh.compute_aabb();
+        let transform =
+            GlobalTransform::from(Transform::from_xyz(10.0, 0.0, 0.0).with_scale(Vec3::splat(2.0)));
+        let ray = Ray {
+            origin: Vec3::new(10.0, 0.0, 10.0),
+            direction: Vec3::NEG_Z,
+        };
+
+        let distance = ray_mesh_distance(ray, &transform, aabb.as_ref(), &mesh).unwrap();
+        assert!((distance - 8.0).abs() < 1e-5);
+
+        let miss = Ray {
+            origin: Vec3::new(0.0, 0.0, 10.0),
+            direction: Vec3::NEG_Z,
+        };
+        assert_eq!(
+            ray_mesh_distance(miss, &transform, aabb.as_ref(), &mesh),
+            None
+        );
+    }
+
+    #[test]
+    fn picking_colors_apply_per_entity() {
+        let style = Handle::weak_from_u128(0x1d6a_83c2_5f0e_4b97_a2c4_7e19_d3b8_0f65);
+
+        let mut app = App::new();
+        app.insert_resource(OutlinePickingSettings {
+            hover_color: Some(Color::YELLOW),
+            select_color: Some(Color::RED),
+            ..default()
+        })
+        .add_systems(Update, apply_picking_styles);
+        let camera = app
+            .world
+            .spawn(CameraOutline {
+                style: style.clone(),
+                ..default()
+            })
+            .id();
+        let hovered = app
+            .world
+            .spawn((
+                OutlineOnHover { hovered: true },
+                OutlineIntensity {
+                    current: 1.0,
+                    ..default()
+                },
+            ))
+            .id();
+        let selected = app
+            .world
+            .spawn((
+                OutlineOnHover::default(),
+                OutlineOnSelect { selected: true },
+            ))
+            .id();
+
+        app.update();
+        let color = |app: &App, entity| app.world.get::<OutlineColor>(entity).copied();
+        assert_eq!(color(&app, hovered), Some(OutlineColor(Color::YELLOW)));
+        assert_eq!(color(&app, selected), Some(OutlineColor(Color::RED)));
+
+        // Selection takes precedence, and the color stays while fading out.
+        app.world
+            .get_mut::<OutlineOnHover>(selected)
+            .unwrap()
+            .hovered = true;
+        app.world
+            .get_mut::<OutlineOnHover>(hovered)
+            .unwrap()
+            .hovered = false;
+        app.update();
+        assert_eq!(color(&app, hovered), Some(OutlineColor(Color::YELLOW)));
+        assert_eq!(color(&app, selected), Some(OutlineColor(Color::RED)));
+
+        app.world
+            .get_mut::<OutlineOnSelect>(selected)
+            .unwrap()
+            .selected = false;
+        app.update();
+        assert_eq!(color(&app, selected), Some(OutlineColor(Color::YELLOW)));
+
+        // Once faded out, the picking color is removed.
+        app.world
+            .get_mut::<OutlineIntensity>(hovered)
+            .unwrap()
+            .current = 0.0;
+        app.update();
+        assert_eq!(color(&app, hovered), None);
+
+        // Without a picking color, an entity keeps its own color.
+        app.world
+            .resource_mut::<OutlinePickingSettings>()
+            .select_color = None;
+        let own_color = app
+            .world
+            .spawn((
+                OutlineOnSelect { selected: true },
+                OutlineColor(Color::BLUE),
+            ))
+            .id();
+        app.update();
+        assert_eq!(color(&app, own_color), Some(OutlineColor(Color::BLUE)));
+
+        // The camera's style is left alone.
+        assert_eq!(app.world.get::<CameraOutline>(camera).unwrap().style, style);
+    }
+
+    #[test]
+    fn picking_colors_restore_own_color() {
+        let mut app = App::new();
+        app.insert_resource(OutlinePickingSettings {
+            hover_color: Some(Color::YELLOW),
+            ..default()
+        })
+        .add_systems(Update, apply_picking_styles);
+        let entity = app
+            .world
+            .spawn((OutlineOnHover::default(), OutlineColor(Color::BLUE)))
+            .id();
+        let color = |app: &App| app.world.get::<OutlineColor>(entity).copied();
+
+        app.update();
+        assert_eq!(color(&app), Some(OutlineColor(Color::BLUE)));
+
+        app.world.get_mut::<OutlineOnHover>(entity).unwrap().hovered = true;
+        app.update();
+        assert_eq!(color(&app), Some(OutlineColor(Color::YELLOW)));
+
+        app.world.get_mut::<OutlineOnHover>(entity).unwrap().hovered = false;
+        app.update();
+        assert_eq!(color(&app), Some(OutlineColor(Color::BLUE)));
+        assert!(app.world.get::<PickingOverrides>(entity).is_none());
+    }
+
+    #[test]
+    fn picking_colors_keep_colors_changed_while_picked() {
+        let mut app = App::new();
+        app.insert_resource(OutlinePickingSettings {
+            hover_color: Some(Color::YELLOW),
+            ..default()
+        })
+        .add_systems(Update, apply_picking_styles);
+        let entity = app
+            .world
+            .spawn((OutlineOnHover { hovered: true }, OutlineColor(Color::BLUE)))
+            .id();
+        let color = |app: &App| app.world.get::<OutlineColor>(entity).copied();
+
+        app.update();
+        assert_eq!(color(&app), Some(OutlineColor(Color::YELLOW)));
+
+        // A color given while picked replaces the saved one, and the picking
+        // color is applied again.
+        app.world
+            .entity_mut(entity)
+            .insert(OutlineColor(Color::GREEN));
+        app.update();
+        assert_eq!(color(&app), Some(OutlineColor(Color::YELLOW)));
+
+        // Changing the picking color doesn't save the previous one.
+        app.world
+            .resource_mut::<OutlinePickingSettings>()
+            .hover_color = Some(Color::RED);
+        app.update();
+        assert_eq!(color(&app), Some(OutlineColor(Color::RED)));
+
+        app.world.get_mut::<OutlineOnHover>(entity).unwrap().hovered = false;
+        app.update();
+        assert_eq!(color(&app), Some(OutlineColor(Color::GREEN)));
+    }
+
+    #[test]
+    fn picking_widths_and_blend_modes_apply_per_state() {
+        let mut app = App::new();
+        app.insert_resource(OutlinePickingSettings {
+            hover_width: Some(4.0),
+            select_width: Some(8.0),
+            select_blend_mode: Some(OutlineBlendMode::Additive),
+            ..default()
+        })
+        .add_systems(Update, apply_picking_styles);
+        let entity = app
+            .world
+            .spawn((
+                OutlineOnHover { hovered: true },
+                OutlineOnSelect::default(),
+                OutlineBlend(OutlineBlendMode::Multiply),
+            ))
+            .id();
+        let width = |app: &App| app.world.get::<OutlineWidth>(entity).copied();
+        let blend = |app: &App| app.world.get::<OutlineBlend>(entity).copied();
+
+        // Without a hover blend mode, the entity keeps its own.
+        app.update();
+        assert_eq!(width(&app), Some(OutlineWidth(4.0)));
+        assert_eq!(blend(&app), Some(OutlineBlend(OutlineBlendMode::Multiply)));
+
+        app.world
+            .get_mut::<OutlineOnSelect>(entity)
+            .unwrap()
+            .selected = true;
+        app.update();
+        assert_eq!(width(&app), Some(OutlineWidth(8.0)));
+        assert_eq!(blend(&app), Some(OutlineBlend(OutlineBlendMode::Additive)));
+
+        app.world
+            .get_mut::<OutlineOnSelect>(entity)
+            .unwrap()
+            .selected = false;
+        app.update();
+        assert_eq!(width(&app), Some(OutlineWidth(4.0)));
+        assert_eq!(blend(&app), Some(OutlineBlend(OutlineBlendMode::Multiply)));
+
+        app.world.get_mut::<OutlineOnHover>(entity).unwrap().hovered = false;
+        app.update();
+        assert_eq!(width(&app), None);
+        assert_eq!(blend(&app), Some(OutlineBlend(OutlineBlendMode::Multiply)));
+    }
+}
@@ -1,50 +1,180 @@
 use bevy::{
+    core_pipeline::prepass::ViewPrepassTextures,
     prelude::*,
     render::{
+        camera::{ExtractedCamera, NormalizedRenderTarget},
+        globals::{GlobalsBuffer, GlobalsUniform},
         render_resource::{
             AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
             BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType,
-            BufferBindingType, DynamicUniformBuffer, Extent3d, FilterMode, Sampler,
-            SamplerBindingType, SamplerDescriptor, ShaderStages, ShaderType, TextureDescriptor,
-            TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureView,
-            TextureViewDimension, UniformBuffer,
+            BufferBindingType, DynamicUniformBuffer, Extent3d, FilterMode, ImageCopyTexture,
+            ImageDataLayout, Origin3d, Sampler, SamplerBindingType, SamplerDescriptor,
+            ShaderStages, ShaderType, Texture, TextureAspect, TextureDescriptor, TextureDimension,
+            TextureFormat, TextureSampleType, TextureUsages, TextureView, TextureViewDimension,
         },
-        renderer::{RenderDevice, RenderQueue},
+        renderer::{RenderAdapter, RenderDevice, RenderQueue},
+        settings::Backends,
         texture::{CachedTexture, TextureCache},
-        view::ExtractedWindows,
+        view::{ExtractedView, ViewUniform, ViewUniforms},
     },
+    utils::HashMap,
 };
 
-use crate::{jfa, outline, OutlineSettings, JFA_TEXTURE_FORMAT};
+use crate::{
+    jfa,
+    mask::{
+        self, ViewPrepasses, MASK_COVERAGE_TEXTURE_FORMAT, MASK_DEPTH_TEXTURE_FORMAT,
+        MASK_ID_TEXTURE_FORMAT, MASK_STENCIL_TEXTURE_FORMAT,
+    },
+    material::ViewOutlineComposite,
+    outline::OutlineBlendMode,
+    CameraOutline, ExtractedOutline, OutlineSettings, JFA_TEXTURE_FORMAT,
+};
 
 const JFA_FROM_PRIMARY: &str = "jfa_from_primary_output_bind_group";
 const JFA_FROM_SECONDARY: &str = "jfa_from_secondary_output_bind_group";
 const JFA_OUTLINE_SRC: &str = "jfa_outline_src_bind_group";
 
+/// Formats the JFA textures can be created with, in order of preference.
+///
+/// `Rg16Snorm` needs `TEXTURE_FORMAT_16BIT_NORM` and isn't renderable on
+/// WebGL2. `Rg32Float` keeps the precision of the seed positions where float
+/// render targets are available, and `Rgba16Float` is the last resort, which
+/// loses sub-pixel precision on large framebuffers.
+const JFA_TEXTURE_FORMATS: [TextureFormat; 3] = [
+    JFA_TEXTURE_FORMAT,
+    TextureFormat::Rg32Float,
+    TextureFormat::Rgba16Float,
+];
+
+/// Sample counts the mask targets can be created with, in descending order.
+const MASK_SAMPLE_COUNTS: [u32; 4] = [8, 4, 2, 1];
+
+/// Format of the table of outline style overrides, indexed by object ID.
+///
+/// The components of a texel hold the overrides of an object, each 0 if it
+/// isn't overridden:
+///
+/// - The color, packed from its sRGB components like the color of an outline
+///   style, with an alpha of 255.
+/// - The bits of the width as an `f32`.
+/// - The shader index of the blend mode.
+const OBJECT_STYLES_TEXTURE_FORMAT: TextureFormat = TextureFormat::Rgba32Uint;
+
+/// Width of the object style table, in object IDs per row. The table has as
+/// many rows as needed to cover the 16-bit ID range.
+const OBJECT_STYLES_WIDTH: u32 = 256;
+
 #[derive(Resource)]
 pub struct OutlineResources {
-    // Multisample target for initial mask pass.
-    pub mask_multisample: CachedTexture,
-    // Resolve target for the above.
-    pub mask_output: CachedTexture,
+    // Format of the JFA textures. If the device can't render to any of
+    // `JFA_TEXTURE_FORMATS`, this is `None` and outlines are disabled.
+    pub jfa_texture_format: Option<TextureFormat>,
+    // Sample count of the mask targets.
+    pub mask_sample_count: u32,
+    // Whether the mask is drawn into a stencil target.
+    pub stencil_mask: bool,
+    // Whether views with depth and normal prepasses build their mask from
+    // them.
+    pub prepass_mask: bool,
 
     pub dimensions_bind_group_layout: BindGroupLayout,
-    pub dimensions_buffer: UniformBuffer<jfa::Dimensions>,
+    // Dimensions of each outlined view, restricted to its viewport.
+    pub dimensions_buffer: DynamicUniformBuffer<jfa::Dimensions>,
     pub dimensions_bind_group: BindGroup,
 
     // Non-filtering sampler for all sampling operations.
     pub sampler: Sampler,
 
-    // Bind group and layout for JFA init pass.
+    // Outline style overrides of the outlined meshes, indexed by object ID.
+    pub object_styles: Texture,
+    pub object_styles_view: TextureView,
+    // Rows of `object_styles` holding overrides since the last frame.
+    pub object_style_rows: u32,
+    // The widest outline width override of the outlined meshes, or 0.
+    pub object_max_width: f32,
+    // Blend modes the outlined meshes are overridden with.
+    pub object_blend_modes: Vec<OutlineBlendMode>,
+
+    // Bind group layouts for JFA init pass, for multisampled and
+    // single-sampled masks.
     pub jfa_init_bind_group_layout: BindGroupLayout,
-    pub jfa_init_bind_group: BindGroup,
+    pub jfa_init_single_sample_bind_group_layout: BindGroupLayout,
+    // Bind group layouts for the view uniform and normal prepass read by the
+    // JFA init pass of prepass masks, for multisampled and single-sampled
+    // prepasses.
+    pub jfa_init_prepass_bind_group_layout: BindGroupLayout,
+    pub jfa_init_single_sample_prepass_bind_group_layout: BindGroupLayout,
 
     // Bind group layout for JFA iteration passes.
     pub jfa_bind_group_layout: BindGroupLayout,
-    // Dynamic uniform buffer containing power-of-two JFA distances from 1 to 32768.
-    // TODO: use instance ID instead?
-    pub jfa_distance_buffer: DynamicUniformBuffer<jfa::JumpDist>,
-    pub jfa_distance_offsets: Vec<u32>,
+
+    // Bind group layouts for sampling JFA results in the outline shader, for
+    // multisampled and single-sampled masks.
+    pub outline_src_bind_group_layout: BindGroupLayout,
+    pub outline_src_single_sample_bind_group_layout: BindGroupLayout,
+
+    // Textures and bind groups for each render target with outlined views.
+    pub targets: HashMap<NormalizedRenderTarget, OutlineTargetResources>,
+}
+
+impl OutlineResources {
+    /// Returns the JFA init bind group layout matching the mask sample count.
+    pub fn jfa_init_layout(&self) -> &BindGroupLayout {
+        match self.mask_sample_count {
+            1 => &self.jfa_init_single_sample_bind_group_layout,
+            _ => &self.jfa_init_bind_group_layout,
+        }
+    }
+
+    /// Returns the JFA init prepass bind group layout matching the sample
+    /// count of the prepass.
+    pub fn jfa_init_prepass_layout(&self, msaa: &Msaa) -> &BindGroupLayout {
+        match msaa.samples() {
+            1 => &self.jfa_init_single_sample_prepass_bind_group_layout,
+            _ => &self.jfa_init_prepass_bind_group_layout,
+        }
+    }
+
+    /// Returns the outline source bind group layout matching the mask sample
+    /// count.
+    pub fn outline_src_layout(&self) -> &BindGroupLayout {
+        match self.mask_sample_count {
+            1 => &self.outline_src_single_sample_bind_group_layout,
+            _ => &self.outline_src_bind_group_layout,
+        }
+    }
+
+    /// Returns the textures and bind groups for the render target of a view.
+    pub fn view_target(&self, world: &World, view: Entity) -> Option<&OutlineTargetResources> {
+        let dims = world.get::<ViewOutlineDimensions>(view)?;
+        self.targets.get(&dims.target)
+    }
+}
+
+/// Textures and bind groups of the outline passes for one render target.
+pub struct OutlineTargetResources {
+    // Dimensions of the outline targets.
+    pub dimensions: jfa::Dimensions,
+    // Sample count the mask targets were created with.
+    mask_sample_count: u32,
+    // Whether the mask targets were created for a stencil mask.
+    stencil_mask: bool,
+
+    // Multisample coverage target for initial mask pass. Without
+    // multisampling, this is the same texture as `mask_output`.
+    pub mask_multisample: CachedTexture,
+    // Resolve target for the above.
+    pub mask_output: CachedTexture,
+    // Multisample object ID target for initial mask pass. For stencil masks,
+    // this is the stencil target, read in place of the object IDs.
+    pub mask_id_multisample: CachedTexture,
+    // Multisample depth target for initial mask pass. For stencil masks, this
+    // is the same texture as `mask_id_multisample`.
+    pub mask_depth_multisample: CachedTexture,
+
+    // Bind group for JFA init pass.
+    pub jfa_init_bind_group: BindGroup,
 
     // Bind group for jump flood passes targeting the primary output.
     pub jfa_from_secondary_bind_group: BindGroup,
@@ -59,28 +189,144 @@ pub struct OutlineResources {
     // Bind groups for the final jump flood pass.
     pub jfa_final_output: CachedTexture,
 
-    // Bind group layout for sampling JFA results in the outline shader.
-    pub outline_src_bind_group_layout: BindGroupLayout,
-    // Bind group layout for outline style parameters.
-    pub outline_params_bind_group_layout: BindGroupLayout,
     pub outline_src_bind_group: BindGroup,
 }
 
-impl OutlineResources {
-    fn create_jfa_bind_group(
-        &self,
+impl OutlineTargetResources {
+    /// Gets the textures for a render target of the given size.
+    ///
+    /// The bind groups of `old` are reused if none of its textures changed.
+    fn prepare(
+        res: &OutlineResources,
         device: &RenderDevice,
-        label: &str,
-        input: &TextureView,
-    ) -> BindGroup {
-        create_jfa_bind_group(
-            device,
-            &self.jfa_bind_group_layout,
-            label,
-            self.jfa_distance_buffer.binding().unwrap(),
-            input,
-            &self.sampler,
-        )
+        textures: &mut TextureCache,
+        size: Extent3d,
+        jfa_format: TextureFormat,
+        old: Option<OutlineTargetResources>,
+    ) -> OutlineTargetResources {
+        let half_size = Extent3d {
+            width: size.width / 2,
+            height: size.height / 2,
+            depth_or_array_layers: 1,
+        };
+
+        let half_resolution = false; // settings.half_resolution;
+        let jfa_size = if half_resolution { half_size } else { size };
+        let sample_count = res.mask_sample_count;
+
+        let mask_output_desc = tex_desc("outline_mask_output", size, MASK_COVERAGE_TEXTURE_FORMAT);
+        let mask_multisample_desc = TextureDescriptor {
+            label: Some("outline_mask_multisample"),
+            sample_count,
+            ..mask_output_desc.clone()
+        };
+        let mask_id_multisample_desc = TextureDescriptor {
+            label: Some("outline_mask_id_multisample"),
+            format: MASK_ID_TEXTURE_FORMAT,
+            ..mask_multisample_desc.clone()
+        };
+        let mask_depth_multisample_desc = TextureDescriptor {
+            label: Some("outline_mask_depth_multisample"),
+            format: MASK_DEPTH_TEXTURE_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT,
+            ..mask_multisample_desc.clone()
+        };
+
+        let mask_output = textures.get(device, mask_output_desc);
+        let mask_multisample = match sample_count {
+            1 => mask_output.clone(),
+            _ => textures.get(device, mask_multisample_desc),
+        };
+        let (mask_id_multisample, mask_depth_multisample) = match res.stencil_mask {
+            true => {
+                let stencil_desc =
+                    tex_desc("outline_mask_stencil", size, MASK_STENCIL_TEXTURE_FORMAT);
+                let stencil = textures.get(device, stencil_desc);
+                (stencil.clone(), stencil)
+            }
+            false => (
+                textures.get(device, mask_id_multisample_desc),
+                textures.get(device, mask_depth_multisample_desc),
+            ),
+        };
+
+        let jfa_primary_desc = tex_desc("outline_jfa_primary_output", jfa_size, jfa_format);
+        let jfa_primary_output = textures.get(device, jfa_primary_desc);
+        let jfa_secondary_desc = tex_desc("outline_jfa_secondary_output", jfa_size, jfa_format);
+        let jfa_secondary_output = textures.get(device, jfa_secondary_desc);
+        let jfa_final_desc = tex_desc("outline_jfa_final_output", size, jfa_format);
+        let jfa_final_output = textures.get(device, jfa_final_desc);
+
+        let unchanged = old.filter(|old| {
+            old.mask_sample_count == sample_count
+                && old.stencil_mask == res.stencil_mask
+                && old.mask_output.texture.id() == mask_output.texture.id()
+                && old.mask_id_multisample.texture.id() == mask_id_multisample.texture.id()
+                && old.jfa_primary_output.texture.id() == jfa_primary_output.texture.id()
+                && old.jfa_secondary_output.texture.id() == jfa_secondary_output.texture.id()
+                && old.jfa_final_output.texture.id() == jfa_final_output.texture.id()
+        });
+
+        let (
+            jfa_init_bind_group,
+            jfa_from_primary_bind_group,
+            jfa_from_secondary_bind_group,
+            outline_src_bind_group,
+        ) = match unchanged {
+            Some(old) => (
+                old.jfa_init_bind_group,
+                old.jfa_from_primary_bind_group,
+                old.jfa_from_secondary_bind_group,
+                old.outline_src_bind_group,
+            ),
+            None => (
+                create_jfa_init_bind_group(
+                    device,
+                    res.jfa_init_layout(),
+                    &mask_id_multisample.default_view,
+                    &mask_output.default_view,
+                ),
+                create_jfa_bind_group(
+                    device,
+                    &res.jfa_bind_group_layout,
+                    JFA_FROM_PRIMARY,
+                    &jfa_primary_output.default_view,
+                    &res.sampler,
+                ),
+                create_jfa_bind_group(
+                    device,
+                    &res.jfa_bind_group_layout,
+                    JFA_FROM_SECONDARY,
+                    &jfa_secondary_output.default_view,
+                    &res.sampler,
+                ),
+                create_outline_src_bind_group(
+                    device,
+                    res,
+                    JFA_OUTLINE_SRC,
+                    &jfa_final_output.default_view,
+                    &mask_output.default_view,
+                    &mask_id_multisample.default_view,
+                ),
+            ),
+        };
+
+        OutlineTargetResources {
+            dimensions: jfa::Dimensions::new(size.width, size.height),
+            mask_sample_count: sample_count,
+            stencil_mask: res.stencil_mask,
+            mask_multisample,
+            mask_output,
+            mask_id_multisample,
+            mask_depth_multisample,
+            jfa_init_bind_group,
+            jfa_from_secondary_bind_group,
+            jfa_primary_output,
+            jfa_from_primary_bind_group,
+            jfa_secondary_output,
+            jfa_final_output,
+            outline_src_bind_group,
+        }
     }
 }
 
@@ -88,38 +334,183 @@ fn create_jfa_bind_group(
     device: &RenderDevice,
     layout: &BindGroupLayout,
     label: &str,
-    dist_buffer: BindingResource,
     input: &TextureView,
     sampler: &Sampler,
 ) -> BindGroup {
-    device.create_bind_group(Some(label),
+    device.create_bind_group(
+        Some(label),
         layout,
         &[
             BindGroupEntry {
                 binding: 0,
-                resource: dist_buffer,
+                resource: BindingResource::TextureView(input),
             },
             BindGroupEntry {
                 binding: 1,
-                resource: BindingResource::TextureView(input),
+                resource: BindingResource::Sampler(sampler),
+            },
+        ],
+    )
+}
+
+fn create_jfa_init_bind_group(
+    device: &RenderDevice,
+    layout: &BindGroupLayout,
+    mask_id: &TextureView,
+    mask: &TextureView,
+) -> BindGroup {
+    device.create_bind_group(
+        Some("outline_jfa_init_bind_group"),
+        layout,
+        &[
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(mask_id),
             },
             BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::TextureView(mask),
+            },
+        ],
+    )
+}
+
+fn create_jfa_init_bind_group_layout(device: &RenderDevice, multisampled: bool) -> BindGroupLayout {
+    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some("outline_jfa_init_bind_group_layout"),
+        entries: &[
+            // Object ID mask
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Uint,
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled,
+                },
+                count: None,
+            },
+            // Resolved coverage mask
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: false },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+fn create_jfa_init_prepass_bind_group_layout(
+    device: &RenderDevice,
+    multisampled: bool,
+) -> BindGroupLayout {
+    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some("outline_jfa_init_prepass_bind_group_layout"),
+        entries: &[
+            // View
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: Some(ViewUniform::min_size()),
+                },
+                count: None,
+            },
+            // Normal prepass
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: false },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled,
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+fn create_outline_src_bind_group_layout(
+    device: &RenderDevice,
+    multisampled: bool,
+) -> BindGroupLayout {
+    device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+        label: Some("jfa_outline_bind_group_layout"),
+        entries: &[
+            // JFA texture
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: false },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            // Mask
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: false },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            // Sampler
+            BindGroupLayoutEntry {
                 binding: 2,
-                resource: BindingResource::Sampler(sampler),
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Sampler(SamplerBindingType::NonFiltering),
+                count: None,
             },
-        ])
+            // Object ID mask
+            BindGroupLayoutEntry {
+                binding: 3,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Uint,
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled,
+                },
+                count: None,
+            },
+            // Object styles
+            BindGroupLayoutEntry {
+                binding: 4,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Uint,
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+        ],
+    })
 }
 
 fn create_outline_src_bind_group(
     device: &RenderDevice,
-    layout: &BindGroupLayout,
+    res: &OutlineResources,
     label: &str,
     src: &TextureView,
     mask: &TextureView,
-    sampler: &Sampler,
+    mask_id: &TextureView,
 ) -> BindGroup {
-    device.create_bind_group(Some(label),
-        layout,
+    device.create_bind_group(
+        Some(label),
+        res.outline_src_layout(),
         &[
             BindGroupEntry {
                 binding: 0,
@@ -131,57 +522,73 @@ fn create_outline_src_bind_group(
             },
             BindGroupEntry {
                 binding: 2,
-                resource: BindingResource::Sampler(sampler),
+                resource: BindingResource::Sampler(&res.sampler),
             },
-        ])
+            BindGroupEntry {
+                binding: 3,
+                resource: BindingResource::TextureView(mask_id),
+            },
+            BindGroupEntry {
+                binding: 4,
+                resource: BindingResource::TextureView(&res.object_styles_view),
+            },
+        ],
+    )
 }
 
 impl FromWorld for OutlineResources {
     fn from_world(world: &mut World) -> Self {
-        let size = Extent3d {
-            width: 1,
-            height: 1,
-            depth_or_array_layers: 1,
-        };
-
         let device = world.get_resource::<RenderDevice>().unwrap().clone();
         let queue = world.get_resource::<RenderQueue>().unwrap().clone();
-        let mut textures = world.get_resource_mut::<TextureCache>().unwrap();
-
-        let mask_output_desc = tex_desc("outline_mask_output", size, TextureFormat::Rgba8Unorm);
-        let mask_multisample_desc = TextureDescriptor {
-            label: Some("outline_mask_multisample"),
-            sample_count: 4,
-            ..mask_output_desc.clone()
-        };
-        let mask_multisample = textures.get(&device, mask_multisample_desc);
-        let mask_output = textures.get(&device, mask_output_desc);
+        let jfa_texture_format = jfa_texture_format(&device, world.resource::<RenderAdapter>());
+        if jfa_texture_format.is_none() {
+            error!(
+                "Outlines are disabled: none of the JFA texture formats {:?} can be rendered to \
+                 and sampled on this device",
+                JFA_TEXTURE_FORMATS
+            );
+        }
 
-        let dims = jfa::Dimensions::new(size.width, size.height);
-        let mut dimensions_buffer = UniformBuffer::from(dims);
+        let mut dimensions_buffer = DynamicUniformBuffer::default();
+        dimensions_buffer.push(jfa::Dimensions::new(1, 1));
         dimensions_buffer.write_buffer(&device, &queue);
 
         let dimensions_bind_group_layout =
             device.create_bind_group_layout(&BindGroupLayoutDescriptor {
                 label: Some("jfa_dimensions_bind_group_layout"),
-                entries: &[BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: ShaderStages::FRAGMENT,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: Some(jfa::Dimensions::min_size()),
+                entries: &[
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: true,
+                            min_binding_size: Some(jfa::Dimensions::min_size()),
+                        },
+                        count: None,
                     },
-                    count: None,
-                }],
+                    // Bevy's globals, for the time in composite shaders
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: Some(GlobalsUniform::min_size()),
+                        },
+                        count: None,
+                    },
+                ],
             });
 
-        let dimensions_bind_group = device.create_bind_group(Some("jfa_dimensions_bind_group"),
+        let mut globals = world.resource_mut::<GlobalsBuffer>();
+        globals.buffer.write_buffer(&device, &queue);
+        let dimensions_bind_group = create_dimensions_bind_group(
+            &device,
             &dimensions_bind_group_layout,
-            &[BindGroupEntry {
-                binding: 0,
-                resource: dimensions_buffer.binding().unwrap(),
-            }]);
+            &dimensions_buffer,
+            &globals,
+        );
 
         let sampler = device.create_sampler(&SamplerDescriptor {
             label: Some("outline_jfa_sampler"),
@@ -195,40 +602,30 @@ impl FromWorld for OutlineResources {
             ..Default::default()
         });
 
-        let jfa_init_bind_group_layout =
-            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-                label: Some("outline_jfa_init_bind_group_layout"),
-                entries: &[
-                    BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: ShaderStages::FRAGMENT,
-                        ty: BindingType::Texture {
-                            sample_type: TextureSampleType::Float { filterable: false },
-                            view_dimension: TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: ShaderStages::FRAGMENT,
-                        ty: BindingType::Sampler(SamplerBindingType::NonFiltering),
-                        count: None,
-                    },
-                ],
-            });
-        let jfa_init_bind_group = device.create_bind_group(Some("outline_jfa_init_bind_group"),
-            &jfa_init_bind_group_layout,
-            &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(&mask_output.default_view),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::Sampler(&sampler),
-                },
-            ]);
+        // Textures are zeroed when created, so no ID has an override yet.
+        let object_styles = device.create_texture(&TextureDescriptor {
+            label: Some("outline_object_styles"),
+            size: Extent3d {
+                width: OBJECT_STYLES_WIDTH,
+                height: (u16::MAX as u32 + 1) / OBJECT_STYLES_WIDTH,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: OBJECT_STYLES_TEXTURE_FORMAT,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let object_styles_view = object_styles.create_view(&default());
+
+        let jfa_init_bind_group_layout = create_jfa_init_bind_group_layout(&device, true);
+        let jfa_init_single_sample_bind_group_layout =
+            create_jfa_init_bind_group_layout(&device, false);
+        let jfa_init_prepass_bind_group_layout =
+            create_jfa_init_prepass_bind_group_layout(&device, true);
+        let jfa_init_single_sample_prepass_bind_group_layout =
+            create_jfa_init_prepass_bind_group_layout(&device, false);
 
         let jfa_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: Some("outline_jfa_bind_group_layout"),
@@ -236,16 +633,6 @@ impl FromWorld for OutlineResources {
                 BindGroupLayoutEntry {
                     binding: 0,
                     visibility: ShaderStages::FRAGMENT,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: true,
-                        min_binding_size: Some(jfa::JumpDist::min_size()),
-                    },
-                    count: None,
-                },
-                BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: ShaderStages::FRAGMENT,
                     ty: BindingType::Texture {
                         sample_type: TextureSampleType::Float { filterable: false },
                         view_dimension: TextureViewDimension::D2,
@@ -254,248 +641,405 @@ impl FromWorld for OutlineResources {
                     count: None,
                 },
                 BindGroupLayoutEntry {
-                    binding: 2,
+                    binding: 1,
                     visibility: ShaderStages::FRAGMENT,
                     ty: BindingType::Sampler(SamplerBindingType::NonFiltering),
                     count: None,
                 },
             ],
         });
-        let mut jfa_distance_buffer = DynamicUniformBuffer::default();
-        let mut jfa_distance_offsets = Vec::new();
-        for exp in 0_u32..16 {
-            // TODO: this should be a DynamicUniformBuffer
-            let ofs = jfa_distance_buffer.push(jfa::JumpDist {
-                dist: 2_u32.pow(exp),
-            });
 
-            jfa_distance_offsets.push(ofs);
-        }
-        jfa_distance_buffer.write_buffer(&device, &queue);
-
-        let jfa_primary_output_desc =
-            tex_desc("outline_jfa_primary_output", size, JFA_TEXTURE_FORMAT);
-        let jfa_primary_output = textures.get(&device, jfa_primary_output_desc);
-        let jfa_secondary_output_desc =
-            tex_desc("outline_jfa_secondary_output", size, JFA_TEXTURE_FORMAT);
-        let jfa_secondary_output = textures.get(&device, jfa_secondary_output_desc);
-        let jfa_final_output_desc = tex_desc("outline_jfa_final_output", size, JFA_TEXTURE_FORMAT);
-        let jfa_final_output = textures.get(&device, jfa_final_output_desc);
-
-        let jfa_from_secondary_bind_group = create_jfa_bind_group(
-            &device,
-            &jfa_bind_group_layout,
-            "outline_jfa_primary_bind_group",
-            jfa_distance_buffer.binding().unwrap(),
-            &jfa_secondary_output.default_view,
-            &sampler,
-        );
-        let jfa_from_primary_bind_group = create_jfa_bind_group(
-            &device,
-            &jfa_bind_group_layout,
-            "outline_jfa_secondary_bind_group",
-            jfa_distance_buffer.binding().unwrap(),
-            &jfa_primary_output.default_view,
-            &sampler,
-        );
-
-        let mut outline_params_buffer = UniformBuffer::from(outline::OutlineParams::new(
-            Color::hex("b4a2c8").unwrap(),
-            Color::WHITE,
-            32.0,
-        ));
-        outline_params_buffer.write_buffer(&device, &queue);
-
-        let outline_src_bind_group_layout =
-            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-                label: Some("jfa_outline_bind_group_layout"),
-                entries: &[
-                    // JFA texture
-                    BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: ShaderStages::FRAGMENT,
-                        ty: BindingType::Texture {
-                            sample_type: TextureSampleType::Float { filterable: false },
-                            view_dimension: TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    // Mask
-                    BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: ShaderStages::FRAGMENT,
-                        ty: BindingType::Texture {
-                            sample_type: TextureSampleType::Float { filterable: false },
-                            view_dimension: TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-                    // Sampler
-                    BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: ShaderStages::FRAGMENT,
-                        ty: BindingType::Sampler(SamplerBindingType::NonFiltering),
-                        count: None,
-                    },
-                ],
-            });
-
-        let outline_params_bind_group_layout =
-            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-                label: Some("jfa_outline_params_bind_group_layout"),
-                entries: &[
-                    // OutlineParams
-                    BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: ShaderStages::FRAGMENT,
-                        ty: BindingType::Buffer {
-                            ty: BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: Some(outline::OutlineParams::min_size()),
-                        },
-                        count: None,
-                    },
-                ],
-            });
-
-        let outline_src_bind_group = create_outline_src_bind_group(
-            &device,
-            &outline_src_bind_group_layout,
-            "jfa_outline_src_bind_group",
-            &jfa_final_output.default_view,
-            &mask_output.default_view,
-            &sampler,
-        );
+        let outline_src_bind_group_layout = create_outline_src_bind_group_layout(&device, true);
+        let outline_src_single_sample_bind_group_layout =
+            create_outline_src_bind_group_layout(&device, false);
 
         OutlineResources {
-            mask_multisample,
-            mask_output,
+            jfa_texture_format,
+            mask_sample_count: 4,
+            stencil_mask: false,
+            prepass_mask: false,
             dimensions_bind_group_layout,
             dimensions_buffer,
             dimensions_bind_group,
             jfa_init_bind_group_layout,
-            jfa_init_bind_group,
+            jfa_init_single_sample_bind_group_layout,
+            jfa_init_prepass_bind_group_layout,
+            jfa_init_single_sample_prepass_bind_group_layout,
             jfa_bind_group_layout,
             sampler,
-            jfa_distance_buffer,
-            jfa_distance_offsets,
-            jfa_primary_output,
-            jfa_secondary_output,
-            jfa_final_output,
-            jfa_from_secondary_bind_group,
-            jfa_from_primary_bind_group,
+            object_styles,
+            object_styles_view,
+            object_style_rows: 0,
+            object_max_width: 0.0,
+            object_blend_modes: Vec::new(),
             outline_src_bind_group_layout,
-            outline_params_bind_group_layout,
-            outline_src_bind_group,
+            outline_src_single_sample_bind_group_layout,
+            targets: HashMap::default(),
         }
     }
 }
 
+/// Prepares the textures and bind groups of each render target with outlined
+/// views.
+///
+/// Resources of targets that no outlined view renders to anymore, such as
+/// closed windows, are dropped.
 pub fn recreate_outline_resources(
     mut outline: ResMut<OutlineResources>,
     device: Res<RenderDevice>,
-    queue: Res<RenderQueue>,
+    adapter: Res<RenderAdapter>,
     mut textures: ResMut<TextureCache>,
-    windows: Res<ExtractedWindows>,
+    settings: Res<OutlineSettings>,
+    msaa: Res<Msaa>,
+    views: Query<&ExtractedCamera, With<CameraOutline>>,
 ) {
-    let Some(primary_entity) = windows.primary else {
-        return
-    };
-
-    let Some(primary) = windows.get(&primary_entity) else {
+    // Without a JFA texture format, no target gets outline resources and the
+    // outline passes are skipped.
+    let Some(jfa_format) = outline.jfa_texture_format else {
+        outline.targets.clear();
         return;
     };
 
-    let half_size = Extent3d {
-        width: primary.physical_width / 2,
-        height: primary.physical_height / 2,
-        depth_or_array_layers: 1,
+    outline.stencil_mask = stencil_mask(&settings, &adapter);
+    outline.prepass_mask = prepass_mask(&settings, &adapter, outline.stencil_mask);
+    // Stencil masks are single-sampled, like the JFA init pass testing them.
+    // Prepass masks are single-sampled so that they skip the multisampled
+    // draw.
+    outline.mask_sample_count = match outline.stencil_mask || outline.prepass_mask {
+        true => 1,
+        false => mask_sample_count(&settings, &msaa, &adapter),
     };
 
-    let size = Extent3d {
-        width: primary.physical_width,
-        height: primary.physical_height,
-        depth_or_array_layers: 1,
-    };
+    let mut old_targets = std::mem::take(&mut outline.targets);
+    let mut targets = HashMap::default();
+    for camera in views.iter() {
+        let (Some(target), Some(target_size)) = (&camera.target, camera.physical_target_size)
+        else {
+            continue;
+        };
+        if target_size.cmpeq(UVec2::ZERO).any() || targets.contains_key(target) {
+            continue;
+        }
+
+        let size = Extent3d {
+            width: target_size.x,
+            height: target_size.y,
+            depth_or_array_layers: 1,
+        };
+        let old = old_targets.remove(target);
+        let resources = OutlineTargetResources::prepare(
+            &outline,
+            &device,
+            &mut textures,
+            size,
+            jfa_format,
+            old,
+        );
+        targets.insert(target.clone(), resources);
+    }
+
+    outline.targets = targets;
+}
+
+/// Writes the outline style overrides of the extracted outlines to the object
+/// style table.
+///
+/// Rows which held overrides in the last frame are cleared.
+pub fn prepare_object_styles(
+    mut outline: ResMut<OutlineResources>,
+    queue: Res<RenderQueue>,
+    outlines: Query<&ExtractedOutline>,
+    mut styles: Local<Vec<u8>>,
+) {
+    let outline = &mut *outline;
+    styles.clear();
+    outline.object_max_width = 0.0;
+    outline.object_blend_modes.clear();
+    // Stencil masks have no object IDs to look the overrides up with.
+    if !outline.stencil_mask {
+        for extracted in outlines.iter() {
+            if extracted.color.is_none()
+                && extracted.width.is_none()
+                && extracted.blend_mode.is_none()
+            {
+                continue;
+            }
+
+            let color = extracted.color.map_or(0, |color| {
+                let [r, g, b, _] = color.as_rgba_u8();
+                u32::from_le_bytes([r, g, b, u8::MAX])
+            });
+            let width = extracted.width.map_or(0, |width| width.max(0.0).to_bits());
+            let blend_mode = extracted.blend_mode.map_or(0, |mode| mode.shader_index());
+            let offset = 16 * extracted.id as usize;
+            if styles.len() < offset + 16 {
+                styles.resize(offset + 16, 0);
+            }
+            for (i, value) in [color, width, blend_mode, 0].into_iter().enumerate() {
+                styles[offset + 4 * i..offset + 4 * (i + 1)].copy_from_slice(&value.to_ne_bytes());
+            }
+
+            if let Some(width) = extracted.width {
+                outline.object_max_width = outline.object_max_width.max(width);
+            }
+            if let Some(mode) = extracted.blend_mode {
+                if !outline.object_blend_modes.contains(&mode) {
+                    outline.object_blend_modes.push(mode);
+                }
+            }
+        }
+    }
 
-    let half_resolution = false; // settings.half_resolution;
-    let jfa_size = if half_resolution { half_size } else { size };
+    let row_size = 16 * OBJECT_STYLES_WIDTH;
+    let rows = (styles.len() as u32).div_ceil(row_size);
+    let written_rows = rows.max(outline.object_style_rows);
+    outline.object_style_rows = rows;
+    if written_rows == 0 {
+        return;
+    }
+
+    styles.resize((written_rows * row_size) as usize, 0);
+    queue.write_texture(
+        ImageCopyTexture {
+            texture: &outline.object_styles,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        },
+        &styles,
+        ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(row_size),
+            rows_per_image: None,
+        },
+        Extent3d {
+            width: OBJECT_STYLES_WIDTH,
+            height: written_rows,
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+/// The dimensions of an outlined view and their offset in the dimensions
+/// uniform buffer.
+#[derive(Clone, Debug, Component)]
+pub struct ViewOutlineDimensions {
+    /// The render target of the view.
+    pub target: NormalizedRenderTarget,
+    /// The dimensions of the outline targets, restricted to the viewport of
+    /// the view.
+    pub dimensions: jfa::Dimensions,
+    pub offset: u32,
+}
+
+/// Writes the dimensions of each outlined view, restricted to its viewport.
+pub fn prepare_view_dimensions(
+    mut commands: Commands,
+    mut outline: ResMut<OutlineResources>,
+    device: Res<RenderDevice>,
+    queue: Res<RenderQueue>,
+    globals: Res<GlobalsBuffer>,
+    views: Query<
+        (
+            Entity,
+            &ExtractedView,
+            &ExtractedCamera,
+            Option<&ViewOutlineComposite>,
+        ),
+        With<CameraOutline>,
+    >,
+) {
+    let outline = &mut *outline;
+    outline.dimensions_buffer.clear();
+    for (entity, view, camera, composite) in views.iter() {
+        let Some((target, resources)) = camera
+            .target
+            .as_ref()
+            .and_then(|target| outline.targets.get_key_value(target))
+        else {
+            continue;
+        };
+
+        // Keep the viewport inside the targets, which may not have been
+        // resized yet.
+        let target_size = UVec2::new(
+            resources.dimensions.width as u32,
+            resources.dimensions.height as u32,
+        );
+        let min = view.viewport.xy().min(target_size);
+        let max = (view.viewport.xy() + view.viewport.zw()).min(target_size);
+        let dimensions = resources
+            .dimensions
+            .with_viewport(UVec4::new(min.x, min.y, max.x - min.x, max.y - min.y))
+            .with_outline_width(composite.map_or(0.0, |composite| composite.width));
+
+        let offset = outline.dimensions_buffer.push(dimensions);
+        commands.entity(entity).insert(ViewOutlineDimensions {
+            target: target.clone(),
+            dimensions,
+            offset,
+        });
+    }
 
-    let new_dims = jfa::Dimensions::new(size.width, size.height);
-    let dims = outline.dimensions_buffer.get_mut();
-    if *dims != new_dims {
-        *dims = new_dims;
-        outline.dimensions_buffer.write_buffer(&device, &queue);
+    // The bind group must stay valid for the pipelines even without views.
+    if outline.dimensions_buffer.is_empty() {
+        outline.dimensions_buffer.push(jfa::Dimensions::new(1, 1));
     }
+    outline.dimensions_buffer.write_buffer(&device, &queue);
 
-    let old_mask = outline.mask_multisample.texture.id();
-    let mask_output_desc = tex_desc("outline_mask_output", size, TextureFormat::Rgba8Unorm);
-    let mask_multisample_desc = TextureDescriptor {
-        label: Some("outline_mask_multisample"),
-        sample_count: 4,
-        ..mask_output_desc.clone()
+    outline.dimensions_bind_group = create_dimensions_bind_group(
+        &device,
+        &outline.dimensions_bind_group_layout,
+        &outline.dimensions_buffer,
+        &globals,
+    );
+}
+
+/// Bind group for the JFA init pass of a view whose mask is built from its
+/// prepass, holding the view uniform and the normal prepass.
+#[derive(Component)]
+pub struct ViewPrepassMask {
+    pub bind_group: BindGroup,
+}
+
+/// Creates the JFA init prepass bind group of each outlined view whose mask is
+/// built from its prepass.
+pub fn prepare_prepass_masks(
+    mut commands: Commands,
+    outline: Res<OutlineResources>,
+    device: Res<RenderDevice>,
+    msaa: Res<Msaa>,
+    view_uniforms: Res<ViewUniforms>,
+    views: Query<(Entity, &ViewPrepassTextures, ViewPrepasses), With<CameraOutline>>,
+) {
+    if !outline.prepass_mask {
+        return;
+    }
+    let Some(view_binding) = view_uniforms.uniforms.binding() else {
+        return;
     };
 
-    // Recreate mask output targets.
-    outline.mask_output = textures.get(&device, mask_output_desc);
-    outline.mask_multisample = textures.get(&device, mask_multisample_desc);
+    for (entity, textures, prepasses) in views.iter() {
+        if !mask::uses_prepass_mask(outline.prepass_mask, mask::view_mesh_key(&msaa, prepasses)) {
+            continue;
+        }
+        let Some(normal) = &textures.normal else {
+            continue;
+        };
 
-    if outline.mask_output.texture.id() != old_mask {
-        // Recreate JFA init pass bind group
-        outline.jfa_init_bind_group = device.create_bind_group(Some("outline_jfa_init_bind_group"),
-            &outline.jfa_init_bind_group_layout,
+        let bind_group = device.create_bind_group(
+            Some("outline_jfa_init_prepass_bind_group"),
+            outline.jfa_init_prepass_layout(&msaa),
             &[
                 BindGroupEntry {
                     binding: 0,
-                    resource: BindingResource::TextureView(&outline.mask_output.default_view),
+                    resource: view_binding.clone(),
                 },
                 BindGroupEntry {
                     binding: 1,
-                    resource: BindingResource::Sampler(&outline.sampler),
+                    resource: BindingResource::TextureView(&normal.default_view),
                 },
-            ]);
-    }
-
-    let old_jfa_primary = outline.jfa_primary_output.texture.id();
-    let jfa_primary_desc = tex_desc("outline_jfa_primary_output", jfa_size, JFA_TEXTURE_FORMAT);
-    let jfa_primary_output = textures.get(&device, jfa_primary_desc);
-    if jfa_primary_output.texture.id() != old_jfa_primary {
-        outline.jfa_primary_output = jfa_primary_output;
-        outline.jfa_from_primary_bind_group = outline.create_jfa_bind_group(
-            &device,
-            JFA_FROM_PRIMARY,
-            &outline.jfa_primary_output.default_view,
+            ],
         );
+        commands
+            .entity(entity)
+            .insert(ViewPrepassMask { bind_group });
     }
+}
 
-    let old_jfa_secondary = outline.jfa_secondary_output.texture.id();
-    let jfa_secondary_desc = tex_desc("outline_jfa_secondary_output", jfa_size, JFA_TEXTURE_FORMAT);
-    let jfa_secondary_output = textures.get(&device, jfa_secondary_desc);
-    if jfa_secondary_output.texture.id() != old_jfa_secondary {
-        outline.jfa_secondary_output = jfa_secondary_output;
-        outline.jfa_from_secondary_bind_group = outline.create_jfa_bind_group(
-            &device,
-            JFA_FROM_SECONDARY,
-            &outline.jfa_secondary_output.default_view,
-        );
+fn create_dimensions_bind_group(
+    device: &RenderDevice,
+    layout: &BindGroupLayout,
+    dimensions: &DynamicUniformBuffer<jfa::Dimensions>,
+    globals: &GlobalsBuffer,
+) -> BindGroup {
+    device.create_bind_group(
+        Some("jfa_dimensions_bind_group"),
+        layout,
+        &[
+            BindGroupEntry {
+                binding: 0,
+                resource: dimensions.binding().unwrap(),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: globals.buffer.binding().unwrap(),
+            },
+        ],
+    )
+}
+
+/// Returns the most preferred JFA texture format that can be rendered to and
+/// sampled on this device, if any.
+///
+/// WebGL2 without `EXT_color_buffer_float` can't render to any of them.
+fn jfa_texture_format(device: &RenderDevice, adapter: &RenderAdapter) -> Option<TextureFormat> {
+    let usages = TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING;
+    JFA_TEXTURE_FORMATS.into_iter().find(|format| {
+        device.features().contains(format.required_features())
+            && adapter
+                .get_texture_format_features(*format)
+                .allowed_usages
+                .contains(usages)
+    })
+}
+
+/// Returns the sample count of the mask targets.
+///
+/// The count set in [`OutlineSettings`], or that of [`Msaa`] if none is set, is
+/// lowered to the highest count the adapter supports for all mask formats.
+fn mask_sample_count(settings: &OutlineSettings, msaa: &Msaa, adapter: &RenderAdapter) -> u32 {
+    // WebGL2 and GLES 3.0 can't sample multisampled textures, even though
+    // the adapter reports multisampled render targets.
+    if Backends::from(adapter.get_info().backend) == Backends::GL {
+        return 1;
     }
 
-    let old_jfa_final = outline.jfa_final_output.texture.id();
-    let jfa_final_desc = tex_desc("outline_jfa_final_output", size, JFA_TEXTURE_FORMAT);
-    let jfa_final_output = textures.get(&device, jfa_final_desc);
-    if jfa_final_output.texture.id() != old_jfa_final {
-        outline.jfa_final_output = jfa_final_output;
-        outline.outline_src_bind_group = create_outline_src_bind_group(
-            &device,
-            &outline.outline_src_bind_group_layout,
-            JFA_OUTLINE_SRC,
-            &outline.jfa_final_output.default_view,
-            &outline.mask_output.default_view,
-            &outline.sampler,
-        );
+    let requested = settings.mask_sample_count().unwrap_or(msaa.samples());
+    let formats = [
+        MASK_COVERAGE_TEXTURE_FORMAT,
+        MASK_ID_TEXTURE_FORMAT,
+        MASK_DEPTH_TEXTURE_FORMAT,
+    ];
+
+    MASK_SAMPLE_COUNTS
+        .into_iter()
+        .filter(|&count| count <= requested)
+        .find(|&count| {
+            formats.iter().all(|&format| {
+                adapter
+                    .get_texture_format_features(format)
+                    .flags
+                    .sample_count_supported(count)
+            })
+        })
+        .unwrap_or(1)
+}
+
+/// Returns whether the mask is drawn into a stencil target.
+///
+/// Stencil masks are used if enabled in [`OutlineSettings`] and the adapter
+/// can sample stencil textures, which WebGL2 can't.
+fn stencil_mask(settings: &OutlineSettings, adapter: &RenderAdapter) -> bool {
+    if !settings.stencil_mask() || Backends::from(adapter.get_info().backend) == Backends::GL {
+        return false;
     }
+
+    adapter
+        .get_texture_format_features(MASK_STENCIL_TEXTURE_FORMAT)
+        .allowed_usages
+        .contains(TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING)
+}
+
+/// Returns whether views with depth and normal prepasses build their mask from
+/// them.
+///
+/// Prepass masks are used if enabled in [`OutlineSettings`], unless stencil
+/// masks are used. WebGL2 can't bind the prepass textures.
+fn prepass_mask(settings: &OutlineSettings, adapter: &RenderAdapter, stencil_mask: bool) -> bool {
+    settings.prepass_mask()
+        && !stencil_mask
+        && Backends::from(adapter.get_info().backend) != Backends::GL
 }
 
 fn tex_desc(label: &'static str, size: Extent3d, format: TextureFormat) -> TextureDescriptor {
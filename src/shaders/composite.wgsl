@@ -0,0 +1,148 @@
+#define_import_path outline::composite
+
+#import outline::dimensions::{dims, clamp_to_viewport}
+#import bevy_render::globals::Globals
+
+@group(0) @binding(1)
+var<uniform> globals: Globals;
+
+@group(1) @binding(0)
+var jfa_buffer: texture_2d<f32>;
+@group(1) @binding(1)
+var mask_buffer: texture_2d<f32>;
+@group(1) @binding(2)
+var nearest_sampler: sampler;
+@group(1) @binding(3)
+#ifdef MASK_MULTISAMPLED
+var mask_id_buffer: texture_multisampled_2d<u32>;
+#else
+var mask_id_buffer: texture_2d<u32>;
+#endif
+@group(1) @binding(4)
+var object_styles: texture_2d<u32>;
+
+// Width of the object style table, in object IDs per row.
+const OBJECT_STYLES_WIDTH: u32 = 256u;
+
+struct OutlineSample {
+    // Fragment position in pixel space.
+    pixel: vec2<f32>,
+    // Whether a seed was found for the fragment.
+    valid: bool,
+    // Nearest seed on the mask edge, in pixel space.
+    nearest_seed: vec2<f32>,
+    // Distance to the nearest seed in pixels.
+    distance: f32,
+    // Coverage of the fragment by the mask.
+    coverage: f32,
+    // Object ID of the fragment in the mask, or 0 if uncovered.
+    object_id: u32,
+    // Outline intensity of the mesh nearest to the fragment, or 0 if its
+    // outline is drawn with another blend mode.
+    intensity: f32,
+    // Outline color override of the mesh nearest to the fragment, with an
+    // alpha of 0 if it has none.
+    color: vec4<f32>,
+    // Outline width of the mesh nearest to the fragment in pixels.
+    width: f32,
+};
+
+// Applies the outline color with the given opacity, in the form expected by
+// the blend state of the pipeline.
+fn blend_output(color: vec3<f32>, alpha: f32) -> vec4<f32> {
+#ifdef BLEND_MULTIPLY
+    // The blend state computes `dst * src.rgb + dst * (1 - src.a)`.
+    return vec4<f32>(color * alpha, alpha);
+#else
+    return vec4<f32>(color, alpha);
+#endif
+}
+
+// Returns the object ID at a pixel of the mask, or 0 if uncovered.
+fn load_object_id(pix: vec2<i32>) -> u32 {
+    return textureLoad(mask_id_buffer, clamp_to_viewport(pix), 0).r;
+}
+
+// Returns the outline intensity of the mesh nearest to a seed.
+//
+// Seeds lie on both sides of an edge, so the mesh covers the seed or one of
+// its neighbors.
+fn nearest_intensity(seed_pix: vec2<f32>) -> f32 {
+    let seed = vec2<i32>(floor(seed_pix));
+
+    var intensity = 0.0;
+    for (var y: i32 = -1; y <= 1; y = y + 1) {
+        for (var x: i32 = -1; x <= 1; x = x + 1) {
+            let pix = clamp_to_viewport(seed + vec2<i32>(x, y));
+            intensity = max(intensity, textureLoad(mask_buffer, pix, 0).g);
+        }
+    }
+    return intensity;
+}
+
+// Returns the object ID of the mesh nearest to a seed, or 0 if none.
+//
+// Of the seed and its neighbors, the covered pixel nearest to the seed is
+// used.
+fn nearest_object_id(seed_pix: vec2<f32>) -> u32 {
+    let seed = vec2<i32>(floor(seed_pix));
+
+    var object_id = 0u;
+    var nearest = 1e9;
+    for (var y: i32 = -1; y <= 1; y = y + 1) {
+        for (var x: i32 = -1; x <= 1; x = x + 1) {
+            let pix = clamp_to_viewport(seed + vec2<i32>(x, y));
+            let id = load_object_id(pix);
+            let dist = distance(seed_pix, vec2<f32>(pix) + 0.5);
+            if id != 0u && dist < nearest {
+                object_id = id;
+                nearest = dist;
+            }
+        }
+    }
+    return object_id;
+}
+
+// Returns the outline style overrides of an object: its packed color, the
+// bits of its width and its blend mode, each 0 if not overridden.
+fn object_style(object_id: u32) -> vec4<u32> {
+    let texel = vec2<u32>(object_id % OBJECT_STYLES_WIDTH, object_id / OBJECT_STYLES_WIDTH);
+    return textureLoad(object_styles, vec2<i32>(texel), 0);
+}
+
+// Returns whether this pipeline draws the outlines of objects with the given
+// blend mode override, or 0 if they have none.
+//
+// Objects overriding the blend mode of the style are drawn by an extra
+// pipeline for their blend mode.
+fn drawn_blend_mode(blend_mode: u32) -> bool {
+#ifdef OBJECT_BLEND_PASS
+    return blend_mode == #{OUTLINE_BLEND_MODE}u;
+#else
+    return blend_mode == 0u || blend_mode == #{OUTLINE_BLEND_MODE}u;
+#endif
+}
+
+// Samples the jump flood result and mask at a fragment.
+fn sample_outline(texcoord: vec2<f32>) -> OutlineSample {
+    let fb_jfa_pos = textureSample(jfa_buffer, nearest_sampler, texcoord).xy;
+    let fb_to_pix = vec2<f32>(dims.width, dims.height);
+
+    var out: OutlineSample;
+    out.pixel = texcoord * fb_to_pix;
+    out.valid = fb_jfa_pos.x != -1.0;
+    // Seeds are placed on the edge of the mask with sub-pixel precision.
+    out.nearest_seed = fb_jfa_pos * fb_to_pix;
+    out.distance = distance(out.pixel, out.nearest_seed);
+    out.coverage = textureSample(mask_buffer, nearest_sampler, texcoord).r;
+    out.object_id = load_object_id(vec2<i32>(out.pixel));
+    out.intensity = nearest_intensity(out.nearest_seed);
+
+    let style = object_style(nearest_object_id(out.nearest_seed));
+    out.color = unpack4x8unorm(style.x);
+    out.width = select(dims.outline_width, bitcast<f32>(style.y), style.y != 0u);
+    if !drawn_blend_mode(style.z) {
+        out.intensity = 0.0;
+    }
+    return out;
+}
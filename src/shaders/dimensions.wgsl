@@ -9,7 +9,18 @@ struct Dimensions {
     inv_width: f32,
     // Reciprocal of height.
     inv_height: f32,
+    // Viewport of the view in pixels, as (x, y, width, height).
+    viewport: vec4<f32>,
+    // Width of the view's outline in pixels.
+    outline_width: f32,
 };
 
 @group(0) @binding(0)
 var<uniform> dims: Dimensions;
+
+// Clamps pixel coordinates to the viewport of the view.
+fn clamp_to_viewport(pix: vec2<i32>) -> vec2<i32> {
+    let min_pix = vec2<i32>(dims.viewport.xy);
+    let max_pix = vec2<i32>(dims.viewport.xy + dims.viewport.zw) - vec2<i32>(1);
+    return clamp(pix, min_pix, max_pix);
+}
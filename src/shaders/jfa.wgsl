@@ -1,21 +1,31 @@
-#import outline::fullscreen::{vertex as fullscreen_vertex, VertexOut}
+#import outline::fullscreen::vertex as fullscreen_vertex
 #import outline::dimensions::dims
 
 // Bind group 0 imported from outline::dimensions
 
+#ifdef JFA_PUSH_CONSTANTS
 struct JumpDist {
     dist: u32,
 };
 
+var<push_constant> jump: JumpDist;
+#endif
+
 @group(1) @binding(0)
-var<uniform> jump_dist: JumpDist;
-@group(1) @binding(1)
 var src_buffer: texture_2d<f32>;
-@group(1) @binding(2)
+@group(1) @binding(1)
 var src_sampler: sampler;
 
+struct VertexOut {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) texcoord: vec2<f32>,
+    // Jump distance in pixels.
+    @location(1) @interpolate(flat) jump_dist: u32,
+};
+
 struct FragmentIn {
     @location(0) texcoord: vec2<f32>,
+    @location(1) @interpolate(flat) jump_dist: u32,
 };
 
 @fragment
@@ -26,8 +36,8 @@ fn fragment(in: FragmentIn) -> @location(0) vec4<f32> {
     let pix_coord = in.texcoord * vec2<f32>(dims.width, dims.height);
 
     // X- and Y-offsets in framebuffer space.
-    let dx = dims.inv_width * f32(jump_dist.dist);
-    let dy = dims.inv_height * f32(jump_dist.dist);
+    let dx = dims.inv_width * f32(in.jump_dist);
+    let dy = dims.inv_height * f32(in.jump_dist);
 
     // TODO: this is actually the largest finite f32. WGSL doesn't seem to have
     // a way to write an infinity float literal.
@@ -69,6 +79,20 @@ fn fragment(in: FragmentIn) -> @location(0) vec4<f32> {
 }
 
 @vertex
-fn vertex(@builtin(vertex_index) idx: u32) -> VertexOut {
-    return fullscreen_vertex(idx);
+fn vertex(
+    @builtin(vertex_index) idx: u32,
+    @builtin(instance_index) instance_idx: u32,
+) -> VertexOut {
+    let fullscreen = fullscreen_vertex(idx);
+
+    var out: VertexOut;
+    out.pos = fullscreen.pos;
+    out.texcoord = fullscreen.texcoord;
+#ifdef JFA_PUSH_CONSTANTS
+    out.jump_dist = jump.dist;
+#else
+    // Without push constants, the jump distance is passed as the instance index.
+    out.jump_dist = instance_idx;
+#endif
+    return out;
 }
@@ -1,80 +1,128 @@
 #import outline::fullscreen::{vertex as fullscreen_vertex, VertexOut}
-#import outline::dimensions::dims
+#import outline::dimensions::{dims, clamp_to_viewport}
+#ifdef PREPASS_MASK
+#import bevy_render::view::View
+#endif
 
 // Jump flood initialization pass.
 @group(1) @binding(0)
-var mask_buffer: texture_2d<f32>;
+#ifdef MASK_MULTISAMPLED
+var mask_id_buffer: texture_multisampled_2d<u32>;
+#else
+var mask_id_buffer: texture_2d<u32>;
+#endif
 @group(1) @binding(1)
-var mask_sampler: sampler;
+var mask_buffer: texture_2d<f32>;
+
+#ifdef PREPASS_MASK
+@group(2) @binding(0)
+var<uniform> view: View;
+@group(2) @binding(1)
+#ifdef PREPASS_MULTISAMPLED
+var normal_prepass_texture: texture_multisampled_2d<f32>;
+#else
+var normal_prepass_texture: texture_2d<f32>;
+#endif
+#endif
 
 struct FragmentIn {
     @location(0) texcoord: vec2<f32>,
 };
 
-@fragment
-fn fragment(in: FragmentIn) -> @location(0) vec4<f32> {
-    let out_position = vec4<f32>(in.texcoord, 0.0, 1.0);
+// Loads the object ID at the given pixel, clamped to the viewport.
+fn load_id(pix: vec2<i32>) -> u32 {
+    return textureLoad(mask_id_buffer, clamp_to_viewport(pix), 0).r;
+}
 
-    // Scaling factor to convert framebuffer to pixel coordinates.
-    let fb_to_pix = vec2<f32>(dims.width, dims.height);
-    // Pixel coordinates of this fragment.
-    let pix_coord = in.texcoord * vec2<f32>(dims.width, dims.height);
-
-    // X- and Y-offsets in framebuffer space.
-    let dx = dims.inv_width;
-    let dy = dims.inv_height;
-
-    // Fetch 9 samples in a 3x3 grid, jump_dist pixels apart.
-    var samples: mat3x3<f32>;
-    var samplesy: mat3x3<f32>;
-    samples[0][0] = textureSample(mask_buffer, mask_sampler, in.texcoord + vec2<f32>(-dx, -dy)).y;
-    samples[0][1] = textureSample(mask_buffer, mask_sampler, in.texcoord + vec2<f32>(-dx, 0.0)).y;
-    samples[0][2] = textureSample(mask_buffer, mask_sampler, in.texcoord + vec2<f32>(-dx, dy)).y;
-    samples[1][0] = textureSample(mask_buffer, mask_sampler, in.texcoord + vec2<f32>(0.0, -dy)).y;
-    samples[1][1] = textureSample(mask_buffer, mask_sampler, in.texcoord).y;
-    samples[1][2] = textureSample(mask_buffer, mask_sampler, in.texcoord + vec2<f32>(0.0, dy)).y;
-    samples[2][0] = textureSample(mask_buffer, mask_sampler, in.texcoord + vec2<f32>(dx, -dy)).y;
-    samples[2][1] = textureSample(mask_buffer, mask_sampler, in.texcoord + vec2<f32>(dx, 0.0)).y;
-    samples[2][2] = textureSample(mask_buffer, mask_sampler, in.texcoord + vec2<f32>(dx, dy)).y;
-    samplesy[0][0] = textureSample(mask_buffer, mask_sampler, in.texcoord + vec2<f32>(-dx, -dy)).z;
-    samplesy[0][1] = textureSample(mask_buffer, mask_sampler, in.texcoord + vec2<f32>(-dx, 0.0)).z;
-    samplesy[0][2] = textureSample(mask_buffer, mask_sampler, in.texcoord + vec2<f32>(-dx, dy)).z;
-    samplesy[1][0] = textureSample(mask_buffer, mask_sampler, in.texcoord + vec2<f32>(0.0, -dy)).z;
-    samplesy[1][1] = textureSample(mask_buffer, mask_sampler, in.texcoord).z;
-    samplesy[1][2] = textureSample(mask_buffer, mask_sampler, in.texcoord + vec2<f32>(0.0, dy)).z;
-    samplesy[2][0] = textureSample(mask_buffer, mask_sampler, in.texcoord + vec2<f32>(dx, -dy)).z;
-    samplesy[2][1] = textureSample(mask_buffer, mask_sampler, in.texcoord + vec2<f32>(dx, 0.0)).z;
-    samplesy[2][2] = textureSample(mask_buffer, mask_sampler, in.texcoord + vec2<f32>(dx, dy)).z;
-
-    if samples[1][0] != samples[1][2] || samples[0][1] != samples[2][1] || samplesy[1][0] != samplesy[1][2] || samplesy[0][1] != samplesy[2][1] || samples[0][0] != samples[2][2] || samplesy[0][0] != samplesy[2][2] {
-        return out_position;
+// Loads the resolved mask coverage at the given pixel, clamped to the
+// viewport.
+fn load_coverage(pix: vec2<i32>) -> f32 {
+    return textureLoad(mask_buffer, clamp_to_viewport(pix), 0).r;
+}
+
+#ifdef PREPASS_MASK
+// Returns the direction of the normal prepass at the given pixel in
+// framebuffer space, which is zero where the normal faces the camera.
+fn prepass_edge_direction(pix: vec2<i32>) -> vec2<f32> {
+    let encoded = textureLoad(normal_prepass_texture, clamp_to_viewport(pix), 0).xyz;
+    let world_normal = encoded * 2.0 - 1.0;
+    let view_normal = (view.inverse_view * vec4<f32>(world_normal, 0.0)).xy;
+    // Framebuffer y points down, unlike view space.
+    return vec2<f32>(view_normal.x, -view_normal.y);
+}
+#endif
+
+// Returns the offset in pixels from the center of a seed pixel to the edge of
+// the mask.
+//
+// The edge normal is estimated from the Sobel gradient of the coverage. For a
+// straight edge, a pixel with coverage `c` has its center `c - 0.5` pixels
+// inside the edge, so the seed is moved that far along the outward normal.
+// Where coverage doesn't change, such as between overlapping objects, the
+// seed stays at the pixel center.
+fn edge_offset(pix: vec2<i32>) -> vec2<f32> {
+    var samples: array<array<f32, 3>, 3>;
+    for (var y: i32 = 0; y < 3; y = y + 1) {
+        for (var x: i32 = 0; x < 3; x = x + 1) {
+            samples[x][y] = load_coverage(pix + vec2<i32>(x - 1, y - 1));
+        }
     }
 
-    return vec4<f32>(-1.0, -1.0, 0.0, 1.0);
+    let sobel_x = samples[2][0] + 2.0 * samples[2][1] + samples[2][2]
+        - samples[0][0] - 2.0 * samples[0][1] - samples[0][2];
+    let sobel_y = samples[0][2] + 2.0 * samples[1][2] + samples[2][2]
+        - samples[0][0] - 2.0 * samples[1][0] - samples[2][0];
+    let gradient = vec2<f32>(sobel_x, sobel_y);
 
-    // if samples[1][1] > 0.99 {
-        // // return out_position;
-        // return vec4<f32>(-1.0, -1.0, 0.0, 1.0);
-    // }
+    if dot(gradient, gradient) < 1e-4 {
+        return vec2<f32>(0.0);
+    }
 
-    // if samples[1][1] < 0.01 {
-        // return vec4<f32>(-1.0, -1.0, 0.0, 1.0);
-    // }
+    // Coverage increases towards the inside of the mask.
+    var outward = -normalize(gradient);
 
-    // let sobel_x = samples[0][0] + 2.0 * samples[0][1] + samples[0][2] - samples[2][0] - 2.0 * samples[2][1] - samples[2][2];
-    // let sobel_y = samples[0][0] + 2.0 * samples[1][0] + samples[2][0] - samples[0][2] - 2.0 * samples[1][2] - samples[2][2];
-	// let sobel = sqrt((sobel_x * sobel_x) + (sobel_y * sobel_y));
-    
-    // var dir = -vec2<f32>(sobel_x, sobel_y);
+#ifdef PREPASS_MASK
+    // Prepass masks are single-sampled, so the gradient only has a few
+    // directions. At silhouettes, the normal prepass gives the exact one.
+    if samples[1][1] > 0.5 {
+        let direction = prepass_edge_direction(pix);
+        if dot(direction, direction) > 1e-4 && dot(direction, outward) > 0.0 {
+            outward = normalize(direction);
+        }
+    }
+#endif
 
-    // if abs(dir.x) < 0.005 && abs(dir.y) < 0.005 {
-        // return out_position;
-    // }
+    return outward * (samples[1][1] - 0.5);
+}
 
-    // dir = normalize(dir);
-    // let offset = dir * (1.0 - samples[1][1]) * vec2<f32>(dx, dy);
+// Returns the framebuffer-space position stored for a seed, moved to the
+// sub-pixel position of the mask edge.
+fn seed_position(texcoord: vec2<f32>, pix: vec2<i32>) -> vec4<f32> {
+    let inv_size = vec2<f32>(dims.inv_width, dims.inv_height);
+    let offset = edge_offset(pix) * inv_size;
+    let min_pos = dims.viewport.xy * inv_size;
+    let max_pos = (dims.viewport.xy + dims.viewport.zw) * inv_size;
+    return vec4<f32>(clamp(texcoord + offset, min_pos, max_pos), 0.0, 1.0);
+}
 
-    // return out_position + vec4<f32>(offset, 0.0, 1.0);
+@fragment
+fn fragment(in: FragmentIn) -> @location(0) vec4<f32> {
+    // Pixel coordinates of this fragment.
+    let pix_coord = vec2<i32>(floor(in.texcoord * vec2<f32>(dims.width, dims.height)));
+
+    // A fragment is a seed if any of its 8 neighbors has a different object ID.
+    // The background has ID 0, so this finds both silhouette edges and edges
+    // between overlapping objects.
+    let center = load_id(pix_coord);
+    for (var y: i32 = -1; y <= 1; y = y + 1) {
+        for (var x: i32 = -1; x <= 1; x = x + 1) {
+            if load_id(pix_coord + vec2<i32>(x, y)) != center {
+                return seed_position(in.texcoord, pix_coord);
+            }
+        }
+    }
+
+    return vec4<f32>(-1.0, -1.0, 0.0, 1.0);
 }
 
 @vertex
@@ -1,40 +1,53 @@
 // Mask generation shader.
 
-#import bevy_pbr::mesh_types::Mesh
-#import bevy_render::view::View
+#import outline::mesh_mask::{mask_vertex_output, VertexOutput}
 
-@group(0) @binding(0) var<uniform> view: View;
-@group(1) @binding(0) var<storage> mesh: array<Mesh>;
+#ifdef PREPASS_MASK
+// Depth prepass of the view, at its binding in Bevy's mesh view bind group.
+#ifdef MULTISAMPLED
+@group(0) @binding(17) var depth_prepass_texture: texture_depth_multisampled_2d;
+#else
+@group(0) @binding(17) var depth_prepass_texture: texture_depth_2d;
+#endif
+
+// Relative depth by which a fragment may lie behind the depth prepass and
+// still be considered visible.
+const PREPASS_DEPTH_TOLERANCE: f32 = 1e-5;
+#endif
 
 struct Vertex {
     @builtin(instance_index) instance_index: u32,
     @location(0) position: vec3<f32>,
 };
 
-struct VertexOutput {
-    @builtin(position) position: vec4<f32>,
-    @location(0) object_origin: vec4<f32>,
-}
-
-fn affine3_to_square(affine: mat3x4<f32>) -> mat4x4<f32> {
-    return transpose(mat4x4<f32>(
-        affine[0],
-        affine[1],
-        affine[2],
-        vec4<f32>(0.0, 0.0, 0.0, 1.0),
-    ));
+struct FragmentOutput {
+    @location(0) coverage: vec4<f32>,
+#ifndef STENCIL_MASK
+    @location(1) id: u32,
+#endif
 }
 
 @vertex
 fn vertex(vertex: Vertex) -> VertexOutput {
-    var out: VertexOutput;
-    out.position = view.view_proj * affine3_to_square(mesh[vertex.instance_index].model) * vec4<f32>(vertex.position, 1.0);
-    // use the object's origin, normalized into screenspace, as its identity for now
-    out.object_origin = view.view_proj * affine3_to_square(mesh[vertex.instance_index].model) * vec4<f32>(0.0, 0.0, 0.0, 1.0);
-    return out;
+    return mask_vertex_output(vertex.instance_index, vertex.position);
 }
 
 @fragment
-fn fragment(fragment: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4(abs(fragment.object_origin).x / 10.0, abs(fragment.object_origin).y / 10.0,  abs(fragment.object_origin).z / 10.0, 1.0);
+fn fragment(fragment: VertexOutput) -> FragmentOutput {
+#ifdef PREPASS_MASK
+    // Bevy uses reverse-Z, so fragments hidden by other geometry have less
+    // depth than the prepass. Mask vertices are transformed as by Bevy's
+    // mesh shaders, so visible fragments match the prepass.
+    let prepass_depth = textureLoad(depth_prepass_texture, vec2<i32>(fragment.position.xy), 0);
+    if fragment.position.z < prepass_depth * (1.0 - PREPASS_DEPTH_TOLERANCE) {
+        discard;
+    }
+#endif
+
+    var out: FragmentOutput;
+    out.coverage = vec4<f32>(1.0, fragment.intensity, 0.0, 1.0);
+#ifndef STENCIL_MASK
+    out.id = fragment.id;
+#endif
+    return out;
 }
@@ -0,0 +1,66 @@
+#define_import_path outline::mesh_mask
+
+#import bevy_render::{
+    globals::Globals,
+    instance_index::get_instance_index,
+    view::View,
+}
+
+struct MeshMask {
+    // Rows of the affine model matrix.
+    model: array<vec4<f32>, 3>,
+    // Object ID. Zero is reserved for the background.
+    id: u32,
+    // Outline intensity, from 0 to 1.
+    intensity: f32,
+};
+
+@group(0) @binding(0) var<uniform> view: View;
+@group(0) @binding(9) var<uniform> globals: Globals;
+
+// The mask data follows the material bind group, if any.
+#ifdef MESH_MASK_BATCH_SIZE
+@group(#{MESH_MASK_BIND_GROUP}) @binding(0) var<uniform> mesh_masks: array<MeshMask, #{MESH_MASK_BATCH_SIZE}u>;
+#else
+@group(#{MESH_MASK_BIND_GROUP}) @binding(0) var<storage> mesh_masks: array<MeshMask>;
+#endif
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) @interpolate(flat) id: u32,
+    @location(1) @interpolate(flat) intensity: f32,
+}
+
+fn affine3_to_square(affine: array<vec4<f32>, 3>) -> mat4x4<f32> {
+    return transpose(mat4x4<f32>(
+        affine[0],
+        affine[1],
+        affine[2],
+        vec4<f32>(0.0, 0.0, 0.0, 1.0),
+    ));
+}
+
+fn get_mesh_mask(instance_index: u32) -> MeshMask {
+    // On WebGL2, the base instance is passed by `DrawMesh` as a push constant.
+    return mesh_masks[get_instance_index(instance_index)];
+}
+
+// Returns the model matrix of an instance.
+fn get_model_matrix(instance_index: u32) -> mat4x4<f32> {
+    return affine3_to_square(get_mesh_mask(instance_index).model);
+}
+
+// Transforms a position in the local space of an instance into the output
+// expected by the mask fragment shader.
+fn mask_vertex_output(instance_index: u32, position: vec3<f32>) -> VertexOutput {
+    let mesh_mask = get_mesh_mask(instance_index);
+
+    // Transformed as by Bevy's mesh shaders, so depth matches the prepass.
+    let world_position = affine3_to_square(mesh_mask.model) * vec4<f32>(position, 1.0);
+
+    var out: VertexOutput;
+    out.position = view.view_proj * vec4<f32>(world_position.xyz, 1.0);
+    out.id = mesh_mask.id;
+    out.intensity = mesh_mask.intensity;
+    return out;
+}
@@ -1,5 +1,5 @@
 #import outline::fullscreen::{vertex as fullscreen_vertex, VertexOut}
-#import outline::dimensions::dims
+#import outline::composite::{sample_outline, blend_output}
 
 struct Params {
     color: vec4<f32>,
@@ -8,13 +8,6 @@ struct Params {
     weight: f32,
 };
 
-@group(1) @binding(0)
-var jfa_buffer: texture_2d<f32>;
-@group(1) @binding(1)
-var mask_buffer: texture_2d<f32>;
-@group(1) @binding(2)
-var nearest_sampler: sampler;
-
 @group(2) @binding(0)
 var<uniform> params: Params;
 
@@ -22,43 +15,78 @@ struct FragmentIn {
     @location(0) texcoord: vec2<f32>,
 };
 
+#ifdef OUTLINE_DEBUG
+// Maps an integer to an arbitrary, well-distributed color.
+fn hash_color(value: u32) -> vec3<f32> {
+    // PCG hash.
+    var x = value * 747796405u + 2891336453u;
+    x = ((x >> ((x >> 28u) + 4u)) ^ x) * 277803737u;
+    x = (x >> 22u) ^ x;
+    return vec3<f32>(vec3<u32>(x, x >> 8u, x >> 16u) & vec3<u32>(0xFFu)) / 255.0;
+}
+
+// Maps [0, 1] to a blue-to-red heatmap.
+fn heatmap(t: f32) -> vec3<f32> {
+    let t4 = 4.0 * clamp(t, 0.0, 1.0);
+    return clamp(1.5 - abs(t4 - vec3<f32>(3.0, 2.0, 1.0)), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// Replaces the composited outline with a visualization of an intermediate
+// result, selected by shader def.
 @fragment
-fn fragment(in: FragmentIn) -> @location(0) vec4<f32> {
-    let fb_jfa_pos = textureSample(jfa_buffer, nearest_sampler, in.texcoord).xy;
-    let fb_to_pix = vec2<f32>(dims.width, dims.height);
+fn debug_fragment(in: FragmentIn) -> @location(0) vec4<f32> {
+    let outline = sample_outline(in.texcoord);
 
-    let mask_value = length(textureSample(mask_buffer, nearest_sampler, in.texcoord).xyz) > 0.0;
+#ifdef DEBUG_MASK
+    return vec4<f32>(vec3<f32>(outline.coverage), 1.0);
+#else ifdef DEBUG_OBJECT_IDS
+    if outline.object_id == 0u {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+    return vec4<f32>(hash_color(outline.object_id), 1.0);
+#else ifdef DEBUG_SEEDS
+    // A fragment is a seed if its nearest seed is itself.
+    if outline.valid && outline.distance < 1.0 {
+        return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+    }
+    return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+#else ifdef DEBUG_VORONOI
+    if !outline.valid {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+    let seed = vec2<u32>(outline.nearest_seed);
+    return vec4<f32>(hash_color(seed.x * 73856093u ^ seed.y * 19349663u), 1.0);
+#else ifdef DEBUG_DISTANCE
+    if !outline.valid {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+    return vec4<f32>(heatmap(outline.distance / (2.0 * outline.width)), 1.0);
+#else
+    return vec4<f32>(1.0, 0.0, 1.0, 1.0);
+#endif
+}
+#endif
 
-    // Fragment position in pixel space.
-    let pix_coord = in.texcoord * fb_to_pix;
-    // Closest initial fragment in pixel space.
-    let pix_jfa_pos = fb_jfa_pos * fb_to_pix;
+@fragment
+fn fragment(in: FragmentIn) -> @location(0) vec4<f32> {
+    let outline = sample_outline(in.texcoord);
+    let mag = outline.distance;
 
-    let delta = pix_coord - pix_jfa_pos;
-    let mag = sqrt(dot(delta, delta));
+    // Width of the anti-aliasing band, in units of distance per pixel.
+    let aa_width = max(fwidth(mag), 1e-3);
 
-    // Computed texcoord and stored texcoord are likely to differ even if they
-    // represent the same position due to storage as fp16, so an epsilon is
-    // needed.
-    if mask_value {
-        let fade = clamp(params.weight - mag * 2.5, 0.1, 0.75);
-        return vec4<f32>(params.color.rgb, fade);
-    } else {
-        let fade = clamp(params.weight * 2.0 - mag, 0.0, 1.0);
-        return vec4<f32>(params.color.rgb, fade);
-    }
+    // Outside the mask, the outline extends twice its width from the edge,
+    // fading out over the anti-aliasing band.
+    let outside = clamp((outline.width * 2.0 - mag) / aa_width + 0.5, 0.0, 1.0);
+    // Inside the mask, the outline fades towards the interior, leaving a
+    // faint tint.
+    let inside = mix(0.75, 0.1, clamp(mag / (outline.width * 0.4), 0.0, 1.0));
 
-    // Computed texcoord and stored texcoord are likely to differ even if they
-    // represent the same position due to storage as fp16, so an epsilon is
-    // needed.
-    // if mask_value < 1.0 {
-        // if mask_value > 0.0 {
-            // return vec4<f32>(params.color.rgb, 1.0 - mask_value);
-        // } else {
-        // }
-    // } else {
-        // return vec4<f32>(0.0, 0.0, 0.0, 0.0); // params.inner_color;
-    // }
+    // Pixels on the edge blend both sides by their coverage.
+    let fade = mix(outside, inside, outline.coverage);
+    // Meshes with a color override replace the color of the style.
+    let color = mix(params.color.rgb, outline.color.rgb, outline.color.a);
+    return blend_output(color, fade * outline.intensity);
 }
 
 @vertex
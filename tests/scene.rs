@@ -0,0 +1,125 @@
+use bevy::{
+    asset::AssetPlugin,
+    prelude::*,
+    render::view::RenderLayers,
+    scene::{serde::SceneDeserializer, DynamicSceneBuilder},
+};
+use bevy_jfa::{
+    CameraOutline, InheritedOutline, JumpSchedule, Outline, OutlineBlend, OutlineBlendMode,
+    OutlineColor, OutlineDebugMode, OutlineGroup, OutlineHierarchy, OutlinePlugin, OutlineSettings,
+    OutlineStyle, OutlineWidth,
+};
+use serde::de::DeserializeSeed;
+
+fn outline_app() -> App {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+        .init_asset::<Shader>()
+        .add_plugins(OutlinePlugin);
+    app.finish();
+    app
+}
+
+#[test]
+fn outlines_round_trip_through_scenes() {
+    let mut app = outline_app();
+    // Offset the entities of the scene from those it is loaded into.
+    app.world.spawn_batch([(), (), ()]);
+
+    let mut settings = OutlineSettings::default();
+    settings.set_jump_schedule(JumpSchedule::Custom(vec![4, 2, 1, 1]));
+    settings.set_debug_mode(Some(OutlineDebugMode::Voronoi));
+    settings.set_stencil_mask(true);
+    settings.set_prepass_mask(true);
+    app.insert_resource(settings.clone());
+
+    let outlined = app
+        .world
+        .spawn((
+            Outline { enabled: true },
+            OutlineGroup(3),
+            OutlineColor(Color::CYAN),
+            OutlineWidth(6.0),
+            OutlineBlend(OutlineBlendMode::Additive),
+            OutlineHierarchy,
+        ))
+        .id();
+    let descendant = app.world.spawn_empty().set_parent(outlined).id();
+    app.update();
+    let style = Handle::weak_from_u128(0x5c8b_ea51_8e6c_4b1f_9a38_2df1_64a0_7c13);
+    app.world
+        .resource_mut::<Assets<OutlineStyle>>()
+        .insert(style.clone(), OutlineStyle::default());
+    let camera = app
+        .world
+        .spawn(CameraOutline {
+            enabled: true,
+            style: style.clone(),
+            layers: Some(RenderLayers::layer(2)),
+        })
+        .id();
+
+    let scene = DynamicSceneBuilder::from_world(&app.world)
+        .extract_entities([outlined, descendant, camera].into_iter())
+        .allow_resource::<OutlineSettings>()
+        .extract_resources()
+        .build();
+
+    let registry = app.world.resource::<AppTypeRegistry>().clone();
+    let serialized = scene.serialize_ron(&registry).unwrap();
+
+    let mut deserializer = ron::de::Deserializer::from_str(&serialized).unwrap();
+    let scene = SceneDeserializer {
+        type_registry: &registry.read(),
+    }
+    .deserialize(&mut deserializer)
+    .unwrap();
+
+    let mut loaded = outline_app();
+    scene
+        .write_to_world(&mut loaded.world, &mut Default::default())
+        .unwrap();
+
+    let mut outlines = loaded.world.query::<(
+        &Outline,
+        &OutlineGroup,
+        &OutlineColor,
+        &OutlineWidth,
+        &OutlineBlend,
+        &OutlineHierarchy,
+    )>();
+    let (outline, group, color, width, blend, _) = outlines.single(&loaded.world);
+    assert_eq!(outline, &Outline { enabled: true });
+    assert_eq!(group, &OutlineGroup(3));
+    assert_eq!(color, &OutlineColor(Color::CYAN));
+    assert_eq!(width, &OutlineWidth(6.0));
+    assert_eq!(blend, &OutlineBlend(OutlineBlendMode::Additive));
+
+    let loaded_root = loaded
+        .world
+        .query_filtered::<Entity, With<OutlineHierarchy>>()
+        .single(&loaded.world);
+    assert_ne!(loaded_root, outlined);
+    let inherited = loaded
+        .world
+        .query::<&InheritedOutline>()
+        .single(&loaded.world);
+    assert_eq!(inherited.root(), loaded_root);
+    assert_eq!(inherited.color(), Some(Color::CYAN));
+    assert_eq!(inherited.width(), Some(6.0));
+    assert_eq!(inherited.blend_mode(), Some(OutlineBlendMode::Additive));
+
+    let camera_outline = loaded
+        .world
+        .query::<&CameraOutline>()
+        .single(&loaded.world)
+        .clone();
+    assert_eq!(camera_outline.layers, Some(RenderLayers::layer(2)));
+    assert_eq!(camera_outline.style.id(), style.id());
+
+    let loaded_settings = loaded.world.resource::<OutlineSettings>();
+    assert_eq!(loaded_settings.jump_schedule(), settings.jump_schedule());
+    assert_eq!(loaded_settings.debug_mode(), settings.debug_mode());
+    assert_eq!(loaded_settings.stencil_mask(), settings.stencil_mask());
+    assert_eq!(loaded_settings.prepass_mask(), settings.prepass_mask());
+}